@@ -1,14 +1,18 @@
 use std::{
     cmp,
-    fs::File,
-    io::{self, stdout, Stdout},
-    path::Path,
-    time::Duration,
+    collections::{BTreeMap, VecDeque},
+    env,
+    fs::{self, File},
+    io::{self, stdout, Stdout, Write},
+    mem,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    time::{Duration, Instant},
 };
 
 use crossterm::{
     cursor::SetCursorStyle,
-    event::{self, Event, KeyCode, KeyEventKind},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     execute,
 };
 use derive_tools::Display;
@@ -16,10 +20,12 @@ use log::{debug, warn, LevelFilter};
 use ratatui::{
     backend::CrosstermBackend,
     buffer::Buffer,
-    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Style, Stylize},
     text::Line,
-    widgets::{Block, Borders, Clear, Paragraph, Widget},
+    widgets::{
+        Block, Borders, Clear, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState, Widget,
+    },
     Terminal,
 };
 use ratatui_macros::{line, vertical};
@@ -27,7 +33,15 @@ use simplelog::{CombinedLogger, WriteLogger};
 use thiserror::Error;
 
 use crate::{
-    document::{Document, DocumentError},
+    clipboard::{Clipboard, ClipboardError},
+    command::{self, Address, CommandError, Global, Range, Substitute},
+    document::{DocLine, Document, DocumentError},
+    layout::Split,
+    motion::{self, CharClass, Find, Motion, RangeKind, TextObject, TextRange},
+    options::{OptionError, Options},
+    register::{Register, Registers},
+    shell,
+    syntax::{self, Token},
     tui,
 };
 
@@ -36,11 +50,57 @@ pub struct App {
     mode: AppMode,
     cursor: Position,
     view_shift: Position,
-    show_help: bool,
     running: bool,
-    doc: Document,
+    options: Options,
+    size: Rect,
+    buffers: Vec<DocBuffer>,
+    buf: usize,
+    next_buf_id: usize,
+    windows: Vec<Window>,
+    win: usize,
+    splits: Split,
+    keys: PendingInput,
     cmd: String,
-    msg: String,
+    cmd_cursor: usize,
+    message: Option<(String, Severity)>,
+    confirm: Option<SubConfirm>,
+    cmd_history: History,
+    completion: Option<Completion>,
+    search: Search,
+    shell_cmd: Option<String>,
+    popup: Option<Popup>,
+    aliases: BTreeMap<String, String>,
+    prev_dir: Option<PathBuf>,
+    syntax_on: bool,
+    registers: Registers,
+    clipboard: Clipboard,
+    last_find: Option<Find>,
+    /// The characters typed over in Replace mode, `None` for those typed past the end of the
+    /// line, for Backspace to bring back
+    replaced: Vec<Option<char>>,
+    /// The keys of the change being typed, which become [`Self::last_change`] once it is done
+    typed: Vec<KeyEvent>,
+    /// The keys of the last change, for `.` to repeat
+    last_change: Vec<KeyEvent>,
+    /// The register a macro is being recorded into with `q`, and the keys typed for it so far
+    recording: Option<(char, Vec<KeyEvent>)>,
+    /// The register of the last macro replayed, for `@@`
+    last_macro: Option<char>,
+    /// Keys waiting to be acted on as if typed, put there by a macro
+    queue: VecDeque<KeyEvent>,
+    block_insert: Option<BlockInsert>,
+    repeated_insert: Option<RepeatedInsert>,
+    /// Where Insert mode was last entered, which Ctrl-w stops at once
+    insert_start: Position,
+    /// Set by Ctrl-o in Insert mode until the one Normal mode command it allows is done, holding
+    /// whether the cursor was at the end of its line to go back there
+    insert_command: Option<bool>,
+    /// The column moving up or down keeps to, past the end of shorter lines
+    want_col: Option<usize>,
+    /// Where the Visual mode selection started, the cursor being its other end
+    anchor: Position,
+    /// The mode, anchor and cursor of the last Visual selection, for `gv`
+    last_visual: Option<(AppMode, Position, Position)>,
 }
 
 #[derive(Debug, Error)]
@@ -51,12 +111,364 @@ pub enum AppError {
     SetLoggerErr(#[from] log::SetLoggerError),
 }
 
-#[derive(Debug, Default, PartialEq, Eq, Display)]
+#[derive(Debug, Error)]
+enum CmdError {
+    #[error("Not an editor command: {0}")]
+    NotACommand(String),
+    #[error("Unsaved changes (add ! to override)")]
+    Unsaved,
+    #[error("Unsaved changes in {} (add ! to override)", .0.join(", "))]
+    UnsavedBuffers(Vec<String>),
+    #[error("No file name")]
+    NoFileName,
+    #[error("{0}")]
+    Option(#[from] OptionError),
+    #[error("No previous pattern")]
+    NoPrevPattern,
+    #[error("Pattern not found: {0}")]
+    PatternNotFound(String),
+    #[error("{0}")]
+    Parse(#[from] CommandError),
+    #[error("Invalid argument: {0}")]
+    InvalidArg(String),
+    #[error("No syntax definition for this file")]
+    NoSyntax,
+    #[error("Cannot move a range of lines into itself")]
+    MoveIntoItself,
+    #[error("Recursive alias: {0}")]
+    RecursiveAlias(String),
+    #[error("Mark not set")]
+    MarkNotSet,
+    #[error("{0}")]
+    Clipboard(#[from] ClipboardError),
+    #[error("Buffer {0} does not exist")]
+    NoSuchBuffer(usize),
+    #[error("Failed to open: {0}")]
+    Open(io::Error),
+    #[error("File is read-only (add ! to override)")]
+    ReadOnly,
+    #[error("Failed to save: {0}")]
+    Save(DocumentError),
+    #[error("{0} written, {} failed: {}", .1.len(), .1.join(", "))]
+    SaveAll(usize, Vec<String>),
+    #[error("Can't change directory: {0}")]
+    ChangeDir(io::Error),
+    #[error("No previous directory")]
+    NoPrevDir,
+    #[error("Failed to run shell: {0}")]
+    Shell(io::Error),
+    #[error("Shell returned {0}")]
+    ShellStatus(ExitStatus),
+    #[error("{0}")]
+    ShellOutput(String),
+}
+
+/// What a command reports back: an optional informative message, or what went wrong.
+type CmdResult = Result<Option<String>, CmdError>;
+
+/// Shown in the status line before the command being typed.
+const CMD_PROMPT: &str = "COMMAND: ";
+
+/// What `:help` shows: sections of keys or commands and what they do.
+const HELP: &[(&str, &[(&str, &str)])] = &[
+    (
+        "Movement",
+        &[
+            ("h j k l", "move the cursor left, down, up, right"),
+            ("arrow keys", "move the cursor"),
+            (
+                "0 ^ $",
+                "move to the start, first non-blank or end of the line",
+            ),
+            (
+                "Home  End",
+                "move to the start or end of the line, in Insert mode too",
+            ),
+            (
+                "[N]+  [N]Enter  [N]-",
+                "move to the first non-blank of the line N below or above",
+            ),
+            (
+                "w b e",
+                "move to the next word, back a word, or to the word's end",
+            ),
+            (
+                "gg  G",
+                "move to the first or last line, or to line N with a count",
+            ),
+            (
+                "f F t T x",
+                "move to the next or previous `x` on the line, or just short of it",
+            ),
+            ("; ,", "repeat the last `f`, `F`, `t` or `T`, or reverse it"),
+            (
+                "/pattern  ?pattern",
+                "move to the next or previous match of `pattern`, or of the last one if empty",
+            ),
+            (
+                "[N]n  [N]N",
+                "move to the Nth match of the last search, the same way or the other",
+            ),
+            (
+                "[N]H  M  [N]L",
+                "move to the top, middle or bottom line of the window, or N lines in",
+            ),
+            ("mx", "set mark `x` at the cursor"),
+            (
+                "`x  'x",
+                "move to mark `x`, or to the first non-blank of its line",
+            ),
+            (
+                "``  `.",
+                "move to where the cursor was before the last jump, or to the last change",
+            ),
+            (
+                "[N]Ctrl-d  [N]Ctrl-u",
+                "scroll down or up half a window, or N lines, taking the cursor along",
+            ),
+            (
+                "[N]Ctrl-f  [N]Ctrl-b",
+                "page N windows down or up, keeping two lines in view",
+            ),
+            (
+                "[N]PageDown  [N]PageUp",
+                "the same as Ctrl-f and Ctrl-b, in Insert mode too",
+            ),
+            (
+                "zt  zz  zb",
+                "scroll the cursor line to the top, middle or bottom of the window",
+            ),
+            ("Ctrl-w h/j/k/l", "move to the window in that direction"),
+            ("Ctrl-w w", "move to the next window"),
+            ("Ctrl-g", "show the file name and cursor position"),
+        ],
+    ),
+    (
+        "Editing",
+        &[
+            ("i", "insert before the cursor"),
+            ("a", "insert after the cursor"),
+            ("R", "type over the text, Backspace bringing it back"),
+            (
+                "v",
+                "select characters as the cursor moves, `v` or Esc to stop",
+            ),
+            (
+                "V",
+                "select whole lines, `v` and `V` switching between the two",
+            ),
+            ("Ctrl-v", "select a block of columns"),
+            ("gv", "select the last Visual selection again"),
+            (
+                "d x  y  c",
+                "in Visual mode, delete, yank or change the selection",
+            ),
+            (
+                "Ctrl-v I  Ctrl-v A",
+                "insert on every line of the block, before or after it",
+            ),
+            (
+                "A  I",
+                "insert at the end of the line, or before its first non-blank",
+            ),
+            ("o  O", "open a line below or above the cursor"),
+            (
+                "Tab  Shift-Tab",
+                "in Insert mode, insert a tab, or spaces with `expandtab`, or dedent the line",
+            ),
+            (
+                "Ctrl-w",
+                "in Insert mode, delete the word before the cursor, stopping once where typing began",
+            ),
+            (
+                "Ctrl-u",
+                "in Insert mode, delete all before the cursor, stopping once where typing began",
+            ),
+            (
+                "Ctrl-o",
+                "in Insert mode, run one Normal mode command and go back to inserting",
+            ),
+            (
+                "[N]i  [N]a  [N]o",
+                "insert what is typed N times over, as do `I`, `A` and `O` with a count",
+            ),
+            ("[N]x  [N]Del", "delete N characters under the cursor"),
+            (
+                "Del",
+                "in Insert mode, delete the character under the cursor, or join the next line up",
+            ),
+            ("[N]X", "delete N characters before the cursor"),
+            ("D", "delete to the end of the line"),
+            ("[N]dd", "delete N lines"),
+            ("[N]yy  [N]Y", "yank N lines"),
+            ("y{motion}", "yank over a motion"),
+            (
+                "[N]p  [N]P",
+                "put what was yanked or deleted N times after or before the cursor",
+            ),
+            (
+                "\"x",
+                "use register `x` for the next yank, delete or put (`X` to append)",
+            ),
+            (
+                "\"+  \"*",
+                "use the system clipboard, e.g. `\"+y` or `\"+p`",
+            ),
+            ("[N]J", "join N lines, at least two, with spaces"),
+            ("[N]>>  [N]<<", "indent or dedent N lines by `shiftwidth`"),
+            (
+                "gu{motion}  gU{motion}",
+                "lowercase or uppercase the text a motion covers",
+            ),
+            ("g~{motion}", "toggle the case of the text a motion covers"),
+            (
+                "iw  aw",
+                "after an operator or in Visual mode, a word, or a word and its blanks",
+            ),
+            (
+                "i\"  a\"  i'  a'",
+                "the text inside quotes on the line, or with the quotes and blanks after",
+            ),
+            (
+                "i(  a(  i{  a{  i[  a[",
+                "the text inside brackets, or with the brackets, `b` and `B` for `(` and `{`",
+            ),
+            (
+                "u  U  ~",
+                "in Visual mode, lowercase, uppercase or toggle the selection",
+            ),
+            (
+                "[N]>  [N]<",
+                "in Visual mode, indent or dedent the selected lines N times",
+            ),
+            (
+                ">{motion}  <{motion}",
+                "indent or dedent the lines a motion covers",
+            ),
+            ("[N]u", "undo the last N changes"),
+            ("[N].", "repeat the last change, N times over if given"),
+            (
+                "qx  q",
+                "record the keys typed into register `x`, until `q`",
+            ),
+            (
+                "[N]@x  [N]@@",
+                "replay the keys in register `x`, or the last ones replayed",
+            ),
+            (
+                "d{motion}",
+                "delete over a motion, e.g. `w`, `e`, `b`, `$` or `G`",
+            ),
+            (
+                "dj  dk  dG  dgg",
+                "delete whole lines down to or up to where `j`, `k`, `G` or `gg` lead",
+            ),
+            ("c{motion}", "change over a motion, like `d` then `i`"),
+            ("[N]cc  [N]S", "change N lines, keeping the indentation"),
+            ("[N]s", "change N characters under the cursor"),
+            ("C", "change to the end of the line"),
+            ("Esc", "go back to Normal mode"),
+            (":", "enter a command"),
+        ],
+    ),
+    (
+        "Commands",
+        &[
+            (
+                ":q[!]",
+                "close the window, or quit vix (! to discard changes)",
+            ),
+            (":qa[!]", "quit vix, closing every window"),
+            (
+                ":w[!] [file]",
+                "save the file, or save it to `file` (! to force)",
+            ),
+            (":wq  :x", "save the file and close the window"),
+            (":wa", "save every modified buffer"),
+            (":wqa  :xa", "save every modified buffer and quit"),
+            (":e[!] [file]", "open `file`, or reload the current one"),
+            (":r file", "insert `file` below the cursor"),
+            (":r !cmd", "insert the output of `cmd` below the cursor"),
+            (":!cmd", "run `cmd` in the shell"),
+            (":N,M!cmd", "filter lines N to M through `cmd`"),
+            (":N", "jump to line N"),
+            (":N,Md [x]", "delete lines N to M, into register `x`"),
+            (":N,My [x]", "yank lines N to M, into register `x`"),
+            (":reg [xy]", "list the contents of registers"),
+            (
+                "Ctrl-r x",
+                "in a command, insert register `x` (`/` for the last pattern)",
+            ),
+            (
+                ":N,Mnormal keys",
+                "type `keys` in Normal mode on lines N to M",
+            ),
+            (":undo [N]", "undo the last N changes"),
+            (":redo [N]", "redo the last N undone changes"),
+            (":earlier N", "go back N changes"),
+            (":later N", "go forward N changes"),
+            (":N,MmK", "move lines N to M below line K"),
+            (":N,MtK", "copy lines N to M below line K"),
+            (
+                ":retab[!] [N]",
+                "redo indentation with `tabstop` and `expandtab`",
+            ),
+            (":s/a/b/[gc]", "replace `a` with `b`"),
+            (
+                ":g/a/cmd",
+                "run `d` or `s/../../` on the lines containing `a`",
+            ),
+            (":v/a/cmd", "run `d` or `s/../../` on the lines without `a`"),
+            (":noh", "stop highlighting the last pattern"),
+            (":set opt", "set an option, e.g. `number` or `tabstop=4`"),
+            (":syntax on|off", "turn syntax highlighting on or off"),
+            (
+                ":command A cmd",
+                "make `:A` run `:cmd`, passing arguments along",
+            ),
+            (":f", "show the file name and cursor position"),
+            (":pwd", "show the working directory"),
+            (
+                ":cd [dir]",
+                "change the working directory (home by default, - for the last)",
+            ),
+            (":mark x", "set mark `x` at the cursor"),
+            (":marks", "list the marks set in the buffer"),
+            (":ls", "list the open buffers"),
+            (":bn  :bp", "switch to the next or previous buffer"),
+            (":bd[!] [N]", "close the current buffer, or buffer N"),
+            (":sp [file]", "split the window horizontally"),
+            (":vsp [file]", "split the window vertically"),
+            (":h", "show this help"),
+        ],
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Severity {
+    Info,
+    Error,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Display)]
 enum AppMode {
     #[default]
     Normal,
     Insert,
+    Replace,
     Command,
+    /// Typing a pattern to search for, edited like a command
+    #[display("Search")]
+    Search {
+        backward: bool,
+    },
+    Confirm,
+    /// Selecting characters from [`App::anchor`] to the cursor
+    Visual,
+    /// Selecting the lines from [`App::anchor`] to the cursor
+    VisualLine,
+    /// Selecting the rectangle with [`App::anchor`] and the cursor at opposite corners
+    VisualBlock,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -69,11 +481,324 @@ enum AppAction {
     EnterMode(AppMode),
     CmdPush(char),
     CmdPop,
+    CmdDelete,
+    CmdDeleteWord,
+    CmdCursor(usize),
     CmdEnter,
+    CmdHistoryPrev,
+    CmdHistoryNext,
+    CmdComplete,
     InsertChar(char),
+    /// Tab in Insert mode, a tab or spaces up to the next tab stop with `expandtab`
+    InsertTab,
+    /// Shift-Tab in Insert mode, dedent the cursor line by `shiftwidth`
+    DedentLine,
+    /// Ctrl-w in Insert mode, delete the word before the cursor without leaving its line
+    DeleteWordBefore,
+    /// Ctrl-u in Insert mode, delete everything before the cursor on its line
+    DeleteLineBefore,
+    /// Ctrl-o in Insert mode, run one Normal mode command and go back to inserting
+    InsertCommand,
+    ReplaceChar(char),
+    JoinLines(usize),
+    Undo(usize),
+    Repeat(Option<usize>),
+    Put {
+        before: bool,
+        count: usize,
+    },
+    ReplaceBackspace,
+    ReplaceNewLine,
     DeleteChar,
     BackspaceLine,
+    /// Delete in Insert mode, delete the character under the cursor, or join the next line up at
+    /// the end of one
+    DeleteForward,
     NewLine,
+    Confirm(ConfirmAnswer),
+    ClosePopup,
+    ScrollHelp(u16),
+    /// Type the number of a buffer in the buffer list, or take the last digit back
+    BufferNumber(Option<usize>),
+    SwitchBuffer(usize),
+    Pending(Pending),
+    Count(usize),
+    SelectRegister(char),
+    Motion(Motion, usize),
+    Operate(Operator, Motion, usize),
+    OperateLines(Operator, usize),
+    /// `Ctrl-d` and `Ctrl-u`, scroll the view and the cursor down or up by half the window, or
+    /// by `lines` if given
+    HalfPage {
+        down: bool,
+        lines: Option<usize>,
+    },
+    /// `Ctrl-f` and `Ctrl-b`, scroll the view down or up a number of windows
+    Page {
+        down: bool,
+        count: usize,
+    },
+    /// `zt`, `zz` and `zb`, scroll the cursor line to the top, middle or bottom of the window
+    PlaceView(ScreenRow),
+    /// Apply an operator to a text object, or select it in Visual mode if there is none
+    Object(Option<Operator>, TextObject, usize),
+    /// `n` and `N`, move to a later match of the last search, the other way round with `reverse`
+    SearchNext {
+        reverse: bool,
+        count: usize,
+    },
+    SetMark(char),
+    /// Move to a mark, or apply an operator up to it, taking in whole lines with `linewise`
+    ToMark {
+        op: Option<Operator>,
+        name: char,
+        linewise: bool,
+    },
+    /// Apply an operator to the Visual mode selection, `count` times over for those shifting
+    /// lines
+    OperateSelection(Operator, usize),
+    /// `gv`, select the last Visual selection again
+    Reselect,
+    /// Insert on every line of a Visual block, before it or after it with `append`
+    BlockInsert {
+        append: bool,
+    },
+    DeleteUnder(usize),
+    /// `X`, delete characters before the cursor without leaving its line
+    DeleteBefore(usize),
+    SubstituteChars(usize),
+    /// `i`, `o`, `O`, `a`, `A` and `I`, with the number of times over to insert the text typed
+    Insert(usize),
+    OpenBelow(usize),
+    OpenAbove(usize),
+    Append(usize),
+    AppendAtEnd(usize),
+    InsertAtIndent(usize),
+    DeleteToEnd,
+    CmdInsert(String),
+    /// Text pasted into the terminal in Insert mode, typed in as a whole
+    InsertText(String),
+    /// Text pasted into the terminal in Normal mode, kept in the unnamed register
+    PasteRegister(String),
+    /// Do nothing but stop the macro being replayed
+    Fail,
+    StartRecording(char),
+    StopRecording,
+    /// Replay the macro in a register, a number of times over
+    Replay(char, usize),
+    FocusWindow(usize),
+    FileInfo,
+}
+
+/// A command partly typed in Normal or Visual mode: the count, the register and the first keys
+/// of a multi-key command typed so far, fed by each key until one completes or cancels it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct PendingInput {
+    pending: Option<Pending>,
+    count: Option<usize>,
+    /// The register chosen with `"x` for the next yank, delete or put
+    register: Option<char>,
+}
+
+impl PendingInput {
+    /// Whether nothing has been typed towards a command yet.
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Add `digit` to the end of the count typed so far.
+    fn push_count(self, digit: char) -> AppAction {
+        let digit = digit.to_digit(10).unwrap_or(0) as usize;
+        let count = self.count.unwrap_or(0);
+        AppAction::Count(count.saturating_mul(10).saturating_add(digit))
+    }
+
+    /// The count of an operator and its motion together, `2d3w` deleting six words.
+    fn combined_count(self, before: Option<usize>) -> Option<usize> {
+        match (before, self.count) {
+            (None, None) => None,
+            (before, after) => Some(before.unwrap_or(1) * after.unwrap_or(1)),
+        }
+    }
+
+    /// What is left waiting for more keys once `action` is acted on: nothing once a command is
+    /// complete, the register only until then.
+    fn after(self, action: &AppAction) -> Self {
+        match *action {
+            // the count typed before `"x` still counts after it
+            AppAction::Pending(Pending::Register) => Self {
+                pending: Some(Pending::Register),
+                ..self
+            },
+            AppAction::Pending(pending) => Self {
+                pending: Some(pending),
+                count: None,
+                ..self
+            },
+            AppAction::Count(count) => Self {
+                count: Some(count),
+                ..self
+            },
+            AppAction::SelectRegister(name) => Self {
+                pending: None,
+                register: Some(name),
+                ..self
+            },
+            _ => Self::default(),
+        }
+    }
+}
+
+/// The first key of a multi-key command, waiting for the rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pending {
+    Window,
+    /// An operator and the count typed before it, waiting for a motion
+    Operator(Operator, Option<usize>),
+    /// `g`, after an operator if any, waiting for the rest
+    G(Option<Operator>, Option<usize>),
+    /// `f`, `F`, `t` or `T`, after an operator if any, waiting for the character to find
+    Find(Option<Operator>, Option<usize>, char),
+    /// `Ctrl-r` in Command mode or `"` in Normal mode, waiting for a register name
+    Register,
+    /// `i` or `a` after an operator or in Visual mode, with the count so far, waiting for the
+    /// kind of text object
+    Object(Option<Operator>, Option<usize>, bool),
+    /// `z`, waiting for where in the window to scroll the cursor line
+    Z,
+    /// `m`, waiting for the mark to set
+    Mark,
+    /// `` ` `` or `'`, after an operator if any, waiting for the mark to move to
+    ToMark(Option<Operator>, char),
+    /// `q`, waiting for the register to record a macro into
+    Record,
+    /// `@` and the count typed before it, waiting for the register of the macro to replay
+    Replay(Option<usize>),
+}
+
+/// What to do with the text a motion covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    /// Delete, then insert in place of the text
+    Change,
+    /// Copy into a register
+    Yank,
+    /// Shift lines right by `shiftwidth`
+    Indent,
+    /// Shift lines left by `shiftwidth`
+    Dedent,
+    /// `gu`, `gU` and `g~`, change the case of letters
+    Case(Case),
+}
+
+/// How a case operator rewrites letters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Case {
+    Lower,
+    Upper,
+    Toggle,
+}
+
+/// A transient window drawn over the document, taking input until it is dismissed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Popup {
+    Help {
+        scroll: u16,
+    },
+    /// The number of the buffer to switch to, as typed so far.
+    Buffers {
+        number: Option<usize>,
+    },
+    Marks,
+    /// The registers to list, all of them if empty.
+    Registers(Vec<char>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConfirmAnswer {
+    Yes,
+    No,
+    All,
+    Quit,
+}
+
+/// An open document, remembering where the cursor was left in it.
+#[derive(Debug)]
+struct DocBuffer {
+    id: usize,
+    doc: Document,
+    cursor: Position,
+    view_shift: Position,
+}
+
+/// A view onto a buffer. The focused window's cursor and view live in `App` while it has focus.
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    buf: usize,
+    cursor: Position,
+    view_shift: Position,
+}
+
+/// Previously entered lines of an input prompt, browsed with Up and Down.
+#[derive(Debug, Default)]
+struct History {
+    entries: Vec<String>,
+    index: Option<usize>,
+    draft: String,
+}
+
+/// Candidates cycled through by repeatedly pressing Tab in Command mode.
+#[derive(Debug)]
+struct Completion {
+    base: String,
+    candidates: Vec<String>,
+    index: usize,
+}
+
+/// The last pattern searched for, which way, and whether its matches are highlighted.
+#[derive(Debug, Default)]
+struct Search {
+    pattern: Option<String>,
+    highlight: bool,
+    /// Whether it was searched for with `?`
+    backward: bool,
+}
+
+/// Text being inserted on the first line of a Visual block with `I` or `A`, to be copied onto
+/// the other lines once done.
+#[derive(Debug, Clone, Copy)]
+struct BlockInsert {
+    /// Where the typing started
+    at: Position,
+    /// The column to insert at on the other lines
+    col: usize,
+    end_row: usize,
+    /// Whether lines too short for the column are padded out to it, or skipped
+    pad: bool,
+}
+
+/// Text being inserted after a count, as with `3i` or `2o`, to be inserted that many times over
+/// once done.
+#[derive(Debug, Clone, Copy)]
+struct RepeatedInsert {
+    /// Where the typing started
+    at: Position,
+    count: usize,
+    /// Whether each copy goes on a line of its own, opened below the last, as for `o` and `O`
+    open: bool,
+}
+
+/// A `:s///c` substitution waiting for the user to confirm each match.
+#[derive(Debug)]
+struct SubConfirm {
+    sub: Substitute,
+    row: usize,
+    col: usize,
+    end: usize,
+    subs: usize,
+    lines: usize,
+    last_sub_row: Option<usize>,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
@@ -82,6 +807,14 @@ pub struct Position {
     pub col: u16,
 }
 
+/// A line of the window: its first, middle or last one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScreenRow {
+    Top,
+    Middle,
+    Bottom,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Move {
     None,
@@ -91,6 +824,38 @@ pub enum Move {
     Down,
 }
 
+impl AppMode {
+    fn is_visual(self) -> bool {
+        matches!(self, Self::Visual | Self::VisualLine | Self::VisualBlock)
+    }
+}
+
+impl Operator {
+    /// Whether the operator acts on whole lines, whatever the motion.
+    fn linewise(self) -> bool {
+        matches!(self, Self::Indent | Self::Dedent)
+    }
+}
+
+impl Case {
+    fn apply(self, text: &str) -> String {
+        match self {
+            Self::Lower => text.to_lowercase(),
+            Self::Upper => text.to_uppercase(),
+            Self::Toggle => {
+                let mut toggled = String::with_capacity(text.len());
+                for ch in text.chars() {
+                    match ch.is_uppercase() {
+                        true => toggled.extend(ch.to_lowercase()),
+                        false => toggled.extend(ch.to_uppercase()),
+                    }
+                }
+                toggled
+            }
+        }
+    }
+}
+
 impl Position {
     pub fn free_move(self, mv: Move) -> Position {
         match mv {
@@ -151,19 +916,118 @@ impl Position {
     }
 }
 
+impl SubConfirm {
+    /// Move to the next match at or after the current position, returning whether there is one.
+    fn seek(&mut self, doc: &Document) -> bool {
+        while self.row <= self.end {
+            if let Some(col) = doc.find_in_line(self.row, &self.sub.pattern, self.col) {
+                self.col = col;
+                return true;
+            }
+            self.row += 1;
+            self.col = 0;
+        }
+        false
+    }
+
+    fn replace(&mut self, doc: &mut Document) {
+        doc.replace_at(
+            self.row,
+            self.col,
+            self.sub.pattern.len(),
+            &self.sub.replacement,
+        );
+        self.subs += 1;
+        if self.last_sub_row != Some(self.row) {
+            self.lines += 1;
+            self.last_sub_row = Some(self.row);
+        }
+        self.advance(self.sub.replacement.len());
+    }
+
+    fn skip(&mut self) {
+        self.advance(self.sub.pattern.len());
+    }
+
+    fn advance(&mut self, len: usize) {
+        if self.sub.global {
+            self.col += len;
+        } else {
+            self.row += 1;
+            self.col = 0;
+        }
+    }
+}
+
+impl DocBuffer {
+    fn new(id: usize, doc: Document) -> Self {
+        Self {
+            id,
+            doc,
+            cursor: Position::default(),
+            view_shift: Position::default(),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self.doc.uri() {
+            Some(uri) => uri.display().to_string(),
+            None => "[No Name]".to_string(),
+        }
+    }
+
+    /// Number, name and modified flag, as shown in status lines.
+    fn label(&self) -> String {
+        let dirty = if self.doc.dirty() { " [+]" } else { "" };
+        format!("{}: {}{}", self.id, self.name(), dirty)
+    }
+}
+
+impl History {
+    fn push(&mut self, entry: &str) {
+        self.index = None;
+        if !entry.is_empty() && self.entries.last().map(String::as_str) != Some(entry) {
+            self.entries.push(entry.to_string());
+        }
+    }
+
+    /// Step back to an older entry, stashing the in-progress `input` when leaving it.
+    fn prev(&mut self, input: &mut String) {
+        let index = match self.index {
+            None if self.entries.is_empty() => return,
+            None => {
+                self.draft = input.clone();
+                self.entries.len() - 1
+            }
+            Some(index) => index.saturating_sub(1),
+        };
+        self.index = Some(index);
+        *input = self.entries[index].clone();
+    }
+
+    /// Step forward to a newer entry, restoring the in-progress input past the newest one.
+    fn next(&mut self, input: &mut String) {
+        match self.index {
+            None => {}
+            Some(index) if index + 1 < self.entries.len() => {
+                self.index = Some(index + 1);
+                *input = self.entries[index + 1].clone();
+            }
+            Some(_) => {
+                self.index = None;
+                *input = std::mem::take(&mut self.draft);
+            }
+        }
+    }
+}
+
 impl App {
     //~ Core Functionality
 
     pub fn open_file(file_path: impl AsRef<Path>) -> io::Result<Self> {
         Ok(Self {
-            mode: AppMode::default(),
-            cursor: Position::default(),
-            view_shift: Position::default(),
-            show_help: true,
-            running: true,
-            doc: Document::open(file_path)?,
-            cmd: String::default(),
-            msg: String::default(),
+            buffers: vec![DocBuffer::new(1, Document::open(file_path)?)],
+            ..Self::default()
         })
     }
 
@@ -171,36 +1035,211 @@ impl App {
         let mut term = tui::init()?;
         init_log()?;
 
+        let mut last_key = Instant::now();
         while self.running {
+            if self.options.timeout
+                && !self.keys.is_empty()
+                && last_key.elapsed() >= Duration::from_millis(self.options.timeoutlen.into())
+            {
+                self.flush_keys();
+            }
+            self.size = term.size()?;
             self.draw(&mut term)?;
             term.show_cursor()?;
-            term.set_cursor(self.cursor.col, self.cursor.row)?;
+            if let Some(prompt) = self.prompt() {
+                let col = prompt.len() + self.cmd[..self.cmd_cursor].chars().count();
+                term.set_cursor(col as u16, self.size.height.saturating_sub(1))?;
+            } else {
+                let area = self.area();
+                let col = self.screen_col(self.cursor, self.view_shift) as u16;
+                term.set_cursor(area.x + col + self.gutter_width(), area.y + self.cursor.row)?;
+            }
             match self.mode {
-                AppMode::Normal => execute!(stdout(), SetCursorStyle::BlinkingBlock)?,
+                AppMode::Normal | AppMode::Visual | AppMode::VisualLine | AppMode::VisualBlock => {
+                    execute!(stdout(), SetCursorStyle::BlinkingBlock)?
+                }
                 AppMode::Insert => execute!(stdout(), SetCursorStyle::BlinkingBar)?,
-                AppMode::Command => execute!(stdout(), SetCursorStyle::SteadyUnderScore)?,
+                AppMode::Replace => execute!(stdout(), SetCursorStyle::BlinkingUnderScore)?,
+                AppMode::Command | AppMode::Search { .. } => {
+                    execute!(stdout(), SetCursorStyle::SteadyUnderScore)?
+                }
+                AppMode::Confirm => execute!(stdout(), SetCursorStyle::SteadyBlock)?,
             }
 
             if event::poll(Duration::from_millis(10))? {
                 let event = event::read()?;
                 debug!("{:?}", event);
-                let action = self.handle_event(event, &term)?;
-                if action != AppAction::None {
-                    self.show_help = false;
-                    self.msg.clear();
+                match event {
+                    Event::Key(_) => {
+                        self.message = None;
+                        last_key = Instant::now();
+                    }
+                    Event::Resize(width, height) => self.size = Rect::new(0, 0, width, height),
+                    _ => {}
                 }
-                debug!("{:?}", action);
-                self.process(action);
+                self.input(event)?;
+            }
+
+            if let Some(cmd) = self.shell_cmd.take() {
+                self.run_shell(&cmd, &mut term)?;
+            }
+        }
+
+        tui::restore()?;
+        Ok(())
+    }
+
+    /// Act on `event` as typed, recording it into the macro being recorded if any, then on the
+    /// keys it queued up.
+    fn input(&mut self, event: Event) -> Result<(), AppError> {
+        if let (Some((_, keys)), Event::Key(key)) = (&mut self.recording, &event) {
+            if key.kind == KeyEventKind::Press {
+                keys.push(*key);
+            }
+        }
+        self.dispatch(event)?;
+        while let Some(key) = self.queue.pop_front() {
+            self.dispatch(Event::Key(key))?;
+            if !self.running {
+                self.queue.clear();
+            }
+        }
+        Ok(())
+    }
+
+    /// Act on `event`, keeping the keys typed for a change so that `.` can repeat it.
+    fn dispatch(&mut self, event: Event) -> Result<(), AppError> {
+        let action = self.handle_event(event.clone())?;
+        debug!("{:?}", action);
+
+        let mode = self.mode;
+        let repeatable = self.popup.is_none() && !matches!(action, AppAction::Repeat(_));
+        let changes = self.doc().change_number();
+        if let Event::Key(key) = event {
+            let typing =
+                matches!(mode, AppMode::Insert | AppMode::Replace) && !self.typed.is_empty();
+            if key.kind == KeyEventKind::Press
+                && repeatable
+                && action != AppAction::InsertCommand
+                && (mode == AppMode::Normal || typing)
+            {
+                self.typed.push(key);
             }
         }
+        let interlude = self.insert_command.is_some();
+        self.process(action);
+        let resumed = interlude && self.resume_insert();
 
+        let unfinished = !self.keys.is_empty();
+        match (mode, self.mode) {
+            _ if !repeatable => self.typed.clear(),
+            // the command run from Insert mode is a change of its own, not the start of one
+            _ if resumed && self.doc().change_number() > changes => {
+                self.last_change = mem::take(&mut self.typed);
+            }
+            _ if resumed => self.typed.clear(),
+            _ if self.typed.is_empty() => {}
+            (AppMode::Normal, AppMode::Normal) if unfinished => {}
+            (_, AppMode::Insert | AppMode::Replace) => {}
+            // done, and a change if it changed the buffer or typed text into it
+            (_, AppMode::Normal)
+                if mode != AppMode::Normal || self.doc().change_number() > changes =>
+            {
+                self.last_change = mem::take(&mut self.typed);
+            }
+            _ => self.typed.clear(),
+        }
+        Ok(())
+    }
+
+    /// Go back to Insert mode once the command Ctrl-o allows is done, returning whether it was.
+    fn resume_insert(&mut self) -> bool {
+        let Some(at_end) = self.insert_command else {
+            return false;
+        };
+        match self.mode {
+            AppMode::Normal if self.keys.is_empty() => {}
+            // the command went into Insert mode itself
+            AppMode::Insert | AppMode::Replace => {
+                self.insert_command = None;
+                return false;
+            }
+            _ => return false,
+        }
+        self.insert_command = None;
+        self.mode = AppMode::Insert;
+        let (row, col) = (self.cursor_row(), self.cursor_col());
+        let len = self.doc().get_line_len(row);
+        if at_end && col == self.col_on_line(row, len, false) {
+            self.jump_to(row, len);
+        }
+        self.insert_start = self.doc_pos();
+        true
+    }
+
+    /// Drop the command typed so far, as if it had been cancelled with Esc.
+    fn flush_keys(&mut self) {
+        self.keys = PendingInput::default();
+        if self.mode == AppMode::Normal {
+            self.typed.clear();
+        }
+    }
+
+    /// Hand the terminal over to `cmd` until it exits and a key is pressed.
+    fn run_shell(
+        &mut self,
+        cmd: &str,
+        term: &mut Terminal<CrosstermBackend<Stdout>>,
+    ) -> Result<(), AppError> {
         tui::restore()?;
+        let result = shell::run(cmd);
+        match &result {
+            Ok(status) if !status.success() => println!("\nshell returned {}", status),
+            Err(err) => println!("\n{}", err),
+            Ok(_) => {}
+        }
+        print!("\nPress any key to continue");
+        stdout().flush()?;
+        tui::wait_for_key()?;
+        tui::enter()?;
+        term.clear()?;
+
+        self.report(match result {
+            Ok(status) if !status.success() => Err(CmdError::ShellStatus(status)),
+            Ok(_) => Ok(None),
+            Err(err) => Err(CmdError::Shell(err)),
+        });
         Ok(())
     }
 
     //~ Processing Logic
 
     fn process(&mut self, action: AppAction) {
+        let (buf, changes) = (self.buf, self.doc().change_number());
+        let inserting = self.mode == AppMode::Insert;
+        let moved = matches!(action, AppAction::CursorViewChange { .. });
+        let navigated = moved || matches!(action, AppAction::Motion(..) | AppAction::Page { .. });
+        let keeps_col = matches!(action, AppAction::Pending(_) | AppAction::Count(_));
+        let to_end = matches!(action, AppAction::Motion(Motion::LineEnd, _));
+        let at = self.doc_pos();
+        self.doc_mut().checkpoint(at);
+        if action != AppAction::CmdComplete {
+            self.completion = None;
+        }
+        // remember an `f`, `F`, `t` or `T` typed out in full for `;` and `,`
+        if let (
+            Some(Pending::Find(..)),
+            AppAction::Motion(Motion::Find(find), _) | AppAction::Operate(_, Motion::Find(find), _),
+        ) = (self.keys.pending, &action)
+        {
+            self.last_find = Some(*find);
+        }
+        // the register chosen is still wanted by the action, so only goes once it is done
+        let keys = self.keys.after(&action);
+        (self.keys.pending, self.keys.count) = (keys.pending, keys.count);
+        if self.mode.is_visual() {
+            self.last_visual = Some((self.mode, self.anchor, self.doc_pos()));
+        }
         match action {
             AppAction::None => {}
             AppAction::CursorViewChange { cursor, view_shift } => {
@@ -210,33 +1249,243 @@ impl App {
                 self.view_shift.col = view_shift.col;
             }
             AppAction::EnterMode(mode) => {
-                if let AppMode::Command = mode {
-                    self.cmd.clear()
+                if let AppMode::Command | AppMode::Search { .. } = mode {
+                    self.cmd.clear();
+                    self.cmd_cursor = 0;
+                    self.cmd_history.index = None;
+                }
+                if let AppMode::Replace = mode {
+                    self.replaced.clear();
+                }
+                // switching between Visual modes keeps the selection
+                if mode.is_visual() && !self.mode.is_visual() {
+                    self.anchor = self.doc_pos();
+                }
+                if let Some(block) = self.block_insert.take().filter(|_| mode == AppMode::Normal) {
+                    self.finish_block_insert(block);
+                }
+                if let Some(repeat) =
+                    (self.repeated_insert.take()).filter(|_| mode == AppMode::Normal)
+                {
+                    self.finish_repeated_insert(repeat);
+                }
+                // back onto the last character typed, like vi
+                if matches!(self.mode, AppMode::Insert | AppMode::Replace)
+                    && mode == AppMode::Normal
+                {
+                    let (row, col) = (self.cursor_row(), self.cursor_col());
+                    let line = self.doc().get_line(row).unwrap_or_default();
+                    let before = line.get(..col).and_then(|ln| ln.chars().next_back());
+                    self.jump_to(row, col - before.map_or(0, char::len_utf8));
                 }
                 self.mode = mode;
             }
             AppAction::CmdPop => {
-                self.cmd.pop();
+                if let Some(ch) = self.cmd[..self.cmd_cursor].chars().next_back() {
+                    self.cmd_cursor -= ch.len_utf8();
+                    self.cmd.remove(self.cmd_cursor);
+                }
+            }
+            AppAction::CmdDelete => {
+                if self.cmd_cursor < self.cmd.len() {
+                    self.cmd.remove(self.cmd_cursor);
+                }
+            }
+            AppAction::CmdDeleteWord => {
+                let start = motion::line_word_back(&self.cmd[..self.cmd_cursor]);
+                self.cmd.replace_range(start..self.cmd_cursor, "");
+                self.cmd_cursor = start;
+            }
+            AppAction::CmdCursor(cursor) => self.cmd_cursor = cursor,
+            AppAction::CmdPush(ch) => {
+                self.cmd.insert(self.cmd_cursor, ch);
+                self.cmd_cursor += ch.len_utf8();
+            }
+            AppAction::CmdInsert(text) => {
+                self.cmd.insert_str(self.cmd_cursor, &text);
+                self.cmd_cursor += text.len();
             }
-            AppAction::CmdPush(ch) => self.cmd.push(ch),
             AppAction::CmdEnter => {
-                self.process_cmd();
-                self.mode = AppMode::Normal;
+                let result = match mem::replace(&mut self.mode, AppMode::Normal) {
+                    AppMode::Search { backward } => self.search(backward),
+                    _ => {
+                        self.cmd_history.push(self.cmd.trim_start());
+                        self.process_cmd()
+                    }
+                };
+                self.report(result);
+            }
+            AppAction::CmdHistoryPrev => {
+                self.cmd_history.prev(&mut self.cmd);
+                self.cmd_cursor = self.cmd.len();
             }
+            AppAction::CmdHistoryNext => {
+                self.cmd_history.next(&mut self.cmd);
+                self.cmd_cursor = self.cmd.len();
+            }
+            AppAction::CmdComplete => self.complete_cmd(),
             AppAction::InsertChar(ch) => {
-                self.doc.insert(self.cursor, ch);
-                self.cursor.col = self.cursor.col.saturating_add(1);
+                let at = self.doc_pos();
+                self.doc_mut().insert(at, ch);
+                self.jump_to(at.row as usize, at.col as usize + ch.len_utf8());
+            }
+            AppAction::InsertText(text) => {
+                let at = self.doc_pos();
+                let end = self.doc_mut().insert_str(at, &line_breaks(&text));
+                self.jump_to(end.row as usize, end.col as usize);
+            }
+            AppAction::PasteRegister(text) => {
+                let content = Register::from_text(line_breaks(&text));
+                self.registers.yank(None, content);
+            }
+            AppAction::InsertTab => {
+                let at = self.doc_pos();
+                let text = match self.options.expandtab {
+                    true => {
+                        let tabstop = (self.options.tabstop as usize).max(1);
+                        let display =
+                            (self.doc()).display_col(at.row as usize, at.col as usize, tabstop);
+                        " ".repeat(tabstop - display % tabstop)
+                    }
+                    false => "\t".to_string(),
+                };
+                let end = self.doc_mut().insert_str(at, &text);
+                self.jump_to(end.row as usize, end.col as usize);
+            }
+            AppAction::DeleteWordBefore => self.delete_back(true),
+            AppAction::DeleteLineBefore => self.delete_back(false),
+            AppAction::InsertCommand => {
+                let (row, col) = (self.cursor_row(), self.cursor_col());
+                self.insert_command = Some(col > 0 && col >= self.doc().get_line_len(row));
+                // a count or a block insert is given up on, as the cursor may go anywhere
+                self.repeated_insert = None;
+                self.block_insert = None;
+                self.mode = AppMode::Normal;
+            }
+            AppAction::DedentLine => {
+                let row = self.cursor_row();
+                self.shift_lines(row, row, -1);
+            }
+            AppAction::ReplaceChar(ch) => {
+                let (row, col) = (self.cursor_row(), self.cursor_col());
+                let line = self.doc().get_line(row).unwrap_or_default();
+                match line.get(col..).and_then(|rest| rest.chars().next()) {
+                    Some(old) => {
+                        let new = ch.to_string();
+                        self.doc_mut().replace_at(row, col, old.len_utf8(), &new);
+                        self.replaced.push(Some(old));
+                    }
+                    None => {
+                        let at = self.doc_pos();
+                        self.doc_mut().insert(at, ch);
+                        self.replaced.push(None);
+                    }
+                }
+                self.jump_to(row, col + ch.len_utf8());
+            }
+            AppAction::ReplaceBackspace => {
+                let (row, col) = (self.cursor_row(), self.cursor_col());
+                let line = self.doc().get_line(row).unwrap_or_default();
+                if let Some(typed) = line
+                    .get(..col)
+                    .and_then(|before| before.chars().next_back())
+                {
+                    let col = col - typed.len_utf8();
+                    match self.replaced.pop() {
+                        Some(Some(old)) => {
+                            let old = old.to_string();
+                            self.doc_mut().replace_at(row, col, typed.len_utf8(), &old);
+                        }
+                        Some(None) => self.doc_mut().delete(Position {
+                            row: row as u16,
+                            col: col as u16,
+                        }),
+                        // typed over before this Replace, so only moved over
+                        None => {}
+                    }
+                    self.jump_to(row, col);
+                }
+            }
+            AppAction::ReplaceNewLine => {
+                self.replaced.clear();
+                let row = self.cursor_row();
+                if row < self.last_row() {
+                    self.jump_to(row + 1, 0);
+                }
+            }
+            AppAction::Put { before, count } => self.put(before, count),
+            AppAction::Repeat(count) => {
+                // a new count takes the place of those typed with the change
+                let keys = match count {
+                    Some(count) => with_count(&self.last_change, count),
+                    None => self.last_change.clone(),
+                };
+                self.feed_keys(&keys);
+            }
+            AppAction::Fail => self.fail(),
+            AppAction::StartRecording(name) => self.recording = Some((name, Vec::new())),
+            AppAction::StopRecording => {
+                if let Some((name, mut keys)) = self.recording.take() {
+                    // the `q` that stopped it
+                    keys.pop();
+                    let text = keys.iter().filter_map(|key| key_text(*key)).collect();
+                    self.registers.record(name, text);
+                }
+            }
+            AppAction::Replay(name, count) => {
+                let name = match name {
+                    '@' => match self.last_macro {
+                        Some(name) => name,
+                        None => return self.fail(),
+                    },
+                    name => name,
+                };
+                let Some(register) = self.registers.get(name) else {
+                    return self.fail();
+                };
+                // lines held in the register are typed as if each ended with Enter
+                let text = match register {
+                    Register::Charwise(text) => text.clone(),
+                    Register::Linewise(lines) | Register::Blockwise(lines) => {
+                        lines.iter().map(|ln| format!("{}\n", ln)).collect()
+                    }
+                };
+                let keys = parse_keys(&text).into_iter().map(|key| match key.code {
+                    KeyCode::Char('\n') => KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
+                    _ => key,
+                });
+                let keys: Vec<_> = keys.collect();
+                // ahead of whatever is left of a macro replaying this one
+                let rest = mem::take(&mut self.queue);
+                for _ in 0..count.max(1) {
+                    self.queue.extend(&keys);
+                }
+                self.queue.extend(rest);
+                self.last_macro = Some(name);
+            }
+            AppAction::Undo(count) => {
+                let (undone, cursor) = self.doc_mut().undo(count);
+                let info = self.undo_result(undone, cursor, true);
+                self.message = Some((info, Severity::Info));
+            }
+            AppAction::JoinLines(count) => {
+                let row = self.cursor_row();
+                if let Some(col) = self.doc_mut().join_lines(row, count) {
+                    self.jump_to(row, col);
+                }
             }
             AppAction::DeleteChar => {
-                self.doc.delete(self.cursor.free_move(Move::Left));
+                let at = self.doc_pos().free_move(Move::Left);
+                self.doc_mut().delete(at);
                 self.cursor.col = self.cursor.col.saturating_sub(1);
             }
             AppAction::BackspaceLine => {
+                let row = self.cursor_row();
                 let col = self
-                    .doc
-                    .get_line_len(self.cursor.row.saturating_sub(1) as usize)
+                    .doc()
+                    .get_line_len(row.saturating_sub(1))
                     .saturating_sub(self.view_shift.col as usize) as u16;
-                self.doc.merge_line_into_up(self.cursor.row as usize);
+                self.doc_mut().merge_line_into_up(row);
                 self.cursor.col = col;
                 if self.cursor.row != 0 {
                     self.cursor.row = self.cursor.row.saturating_sub(1);
@@ -244,156 +1493,2207 @@ impl App {
                     self.view_shift.row = self.view_shift.row.saturating_sub(1);
                 }
             }
+            AppAction::DeleteForward => {
+                let at = self.doc_pos();
+                if (at.col as usize) < self.doc().get_line_len(at.row as usize) {
+                    self.doc_mut().delete(at);
+                } else {
+                    self.doc_mut().merge_line_into_up(at.row as usize + 1);
+                }
+            }
             AppAction::NewLine => {
-                self.doc.split_to_two_line(self.cursor);
+                let at = self.doc_pos();
+                self.doc_mut().split_to_two_line(at);
                 self.cursor.col = 0;
                 self.cursor.row = self.cursor.row.saturating_add(1);
             }
-        };
-    }
-
-    fn process_cmd(&mut self) {
-        let cmd: Vec<&str> = self.cmd.split(' ').collect();
-        if cmd.is_empty() {
-            return;
-        }
-        match cmd[0] {
-            "q" | "quit" | "exit" => {
-                if !self.doc.dirty() {
-                    self.running = false;
-                } else {
-                    self.msg =
-                        "Unsaved changes, use `q!` to force a quit without saving".to_string();
+            AppAction::Confirm(answer) => self.answer_confirm(answer),
+            AppAction::ClosePopup => self.popup = None,
+            AppAction::ScrollHelp(scroll) => self.popup = Some(Popup::Help { scroll }),
+            AppAction::BufferNumber(number) => self.popup = Some(Popup::Buffers { number }),
+            AppAction::SwitchBuffer(id) => {
+                self.popup = None;
+                match self.buffers.iter().position(|buf| buf.id == id) {
+                    Some(ind) => self.switch_buffer(ind),
+                    None => self.report(Err(CmdError::NoSuchBuffer(id))),
                 }
             }
-            "q!" | "quit!" => self.running = false,
-            "h" | "help" => self.show_help = true,
-            "w" | "write" => {
-                if cmd.len() > 1 {
-                    self.doc.set_uri(cmd[1]);
-                }
-                if let Err(DocumentError::NoUri) = self.doc.save() {
-                    self.msg =
-                        "No URI is specified, use `:w path/file.txt` to save to `path/file.txt`"
-                            .to_string();
+            AppAction::Pending(pending) => self.keys.pending = Some(pending),
+            AppAction::Count(count) => self.keys.count = Some(count),
+            AppAction::SelectRegister(name) => {
+                // `"+` is read afresh each time, as other applications may have copied since
+                if Registers::is_clipboard(name) {
+                    match self.clipboard.paste() {
+                        Ok(content) => self.registers.set_clipboard(content),
+                        Err(err) => self.report(Err(err.into())),
+                    }
                 }
             }
-            "wq" => {
-                if let Err(DocumentError::NoUri) = self.doc.save() {
-                    self.msg =
-                        "No URI is specified, use `:w path/file.txt` to save to `path/file.txt`"
-                            .to_string();
-                    return;
+            AppAction::Motion(motion, count) => self.move_by(motion, count),
+            AppAction::Operate(op, motion, count) => self.operate(op, motion, count),
+            AppAction::HalfPage { down, lines } => {
+                let lines = lines.unwrap_or(self.area().height as usize / 2).max(1);
+                self.scroll_with_cursor(down, lines);
+            }
+            AppAction::Page { down, count } => self.page(down, count),
+            AppAction::PlaceView(at) => self.place_view(at),
+            AppAction::Object(op, object, count) => self.operate_object(op, object, count),
+            AppAction::SearchNext { reverse, count } => {
+                let result = self.search_next(reverse, count);
+                self.report(result);
+            }
+            AppAction::SetMark(name) => {
+                let pos = self.doc_pos();
+                self.doc_mut().set_mark(name, pos);
+            }
+            AppAction::ToMark { op, name, linewise } => {
+                let Some(pos) = self.doc().mark(name) else {
+                    return self.report(Err(CmdError::MarkNotSet));
+                };
+                let motion = Motion::Mark { pos, linewise };
+                match op {
+                    Some(op) => self.operate(op, motion, 1),
+                    None => self.move_by(motion, 1),
                 }
-                self.running = false;
             }
-            _ => {}
-        }
-    }
-
-    //~ Rendering Logic
-
-    fn draw(&self, term: &mut Terminal<CrosstermBackend<Stdout>>) -> Result<(), AppError> {
-        term.draw(|frame| {
-            let area = frame.size();
-
-            let [main_area, status_area] = vertical![*=1, ==1].areas(area);
-            frame.render_widget(self, main_area);
-
-            let status_line = match self.mode {
-                AppMode::Normal => {
-                    if self.msg.is_empty() {
-                        "NORMAL".to_string()
-                    } else {
-                        self.msg.clone()
+            AppAction::OperateLines(op, count) => {
+                let start = self.cursor_row();
+                self.apply(op, TextRange::lines(start, start + count.max(1) - 1));
+            }
+            AppAction::OperateSelection(op, count) => {
+                let (start, end) = self.selection();
+                match mem::replace(&mut self.mode, AppMode::Normal) {
+                    // `N>` shifts the lines N levels over
+                    _ if op.linewise() => {
+                        let row = start.row as usize;
+                        for _ in 0..count.max(1) {
+                            self.apply(op, TextRange::lines(row, end.row as usize));
+                        }
+                        self.jump_to(row, self.first_non_blank(row));
                     }
-                }
-                AppMode::Command => format!("COMMAND: {}", self.cmd),
-                AppMode::Insert => "INSERT".to_string(),
-            };
-            let status_style = match self.mode {
-                AppMode::Normal => {
-                    if self.msg.is_empty() {
-                        Style::default().bold().on_light_blue()
-                    } else {
-                        Style::default().bold().on_red()
+                    AppMode::VisualBlock => {
+                        let (start, end) = self.block();
+                        self.apply(op, TextRange::block(start, end));
                     }
+                    AppMode::VisualLine => {
+                        let range = TextRange::lines(start.row as usize, end.row as usize);
+                        self.apply(op, range);
+                    }
+                    _ => {
+                        // the character under the end is taken in, or the line break past it
+                        let line = self.doc().get_line(end.row as usize).unwrap_or_default();
+                        let under =
+                            (line.get(end.col as usize..)).and_then(|rest| rest.chars().next());
+                        let end = match under {
+                            Some(ch) => Position {
+                                row: end.row,
+                                col: end.col + ch.len_utf8() as u16,
+                            },
+                            None if (end.row as usize) < self.last_row() => Position {
+                                row: end.row + 1,
+                                col: 0,
+                            },
+                            None => end,
+                        };
+                        self.apply(op, TextRange::chars(start, end));
+                    }
+                }
+            }
+            AppAction::Reselect => {
+                if let Some((mode, anchor, cursor)) = self.last_visual {
+                    self.anchor = Position {
+                        row: anchor.row.min(self.last_row() as u16),
+                        ..anchor
+                    };
+                    self.jump_to(cursor.row as usize, cursor.col as usize);
+                    self.mode = mode;
+                }
+            }
+            AppAction::BlockInsert { append } => {
+                let (start, end) = self.block();
+                self.insert_in_block(start, end, append);
+            }
+            AppAction::DeleteUnder(count) => {
+                let at = self.doc_pos();
+                let removed = self.doc_mut().delete_chars(at, count);
+                if !removed.is_empty() {
+                    self.registers
+                        .delete(self.keys.register, Register::Charwise(removed));
+                }
+                self.clamp_to_last_char();
+            }
+            AppAction::DeleteBefore(count) => {
+                let at = self.doc_pos();
+                let removed = self.doc_mut().delete_chars_before(at, count);
+                let col = at.col as usize - removed.len();
+                if !removed.is_empty() {
+                    self.registers
+                        .delete(self.keys.register, Register::Charwise(removed));
+                }
+                self.jump_to(at.row as usize, col);
+            }
+            AppAction::SubstituteChars(count) => {
+                let at = self.doc_pos();
+                let removed = self.doc_mut().delete_chars(at, count);
+                if !removed.is_empty() {
+                    self.registers
+                        .delete(self.keys.register, Register::Charwise(removed));
+                }
+                self.mode = AppMode::Insert;
+            }
+            AppAction::Insert(count) => {
+                self.mode = AppMode::Insert;
+                self.repeat_insert(count, false);
+            }
+            AppAction::OpenBelow(count) => {
+                self.open_line(self.cursor_row() + 1);
+                self.repeat_insert(count, true);
+            }
+            // the cursor stays on its screen row, with the lines below pushed down
+            AppAction::OpenAbove(count) => {
+                self.open_line(self.cursor_row());
+                self.repeat_insert(count, true);
+            }
+            AppAction::Append(count) => {
+                let (row, col) = (self.cursor_row(), self.cursor_col());
+                let line = self.doc().get_line(row).unwrap_or_default();
+                let under = line.get(col..).and_then(|rest| rest.chars().next());
+                self.jump_to(row, col + under.map_or(0, char::len_utf8));
+                self.mode = AppMode::Insert;
+                self.repeat_insert(count, false);
+            }
+            AppAction::AppendAtEnd(count) => {
+                let row = self.cursor_row();
+                self.jump_to(row, self.doc().get_line_len(row));
+                self.mode = AppMode::Insert;
+                self.repeat_insert(count, false);
+            }
+            AppAction::InsertAtIndent(count) => {
+                let row = self.cursor_row();
+                self.jump_to(row, self.first_non_blank(row));
+                self.mode = AppMode::Insert;
+                self.repeat_insert(count, false);
+            }
+            AppAction::DeleteToEnd => {
+                let (row, col) = (self.cursor_row(), self.cursor_col());
+                let removed = self.doc_mut().truncate_line(row, col);
+                if !removed.is_empty() {
+                    self.registers
+                        .delete(self.keys.register, Register::Charwise(removed));
+                }
+                self.clamp_to_last_char();
+            }
+            AppAction::FocusWindow(ind) => self.focus_window(ind),
+            AppAction::FileInfo => self.report(Ok(Some(self.file_info()))),
+        };
+        // moving up or down keeps to the column the cursor was in before, or to the end after `$`
+        let pos = self.doc_pos();
+        if moved && pos.row != at.row {
+            let col = *self.want_col.get_or_insert(at.col as usize);
+            let past_end = matches!(self.mode, AppMode::Insert | AppMode::Replace);
+            let col = self.col_on_line(pos.row as usize, col, past_end);
+            self.jump_to(pos.row as usize, col);
+        } else if !keeps_col {
+            self.want_col = to_end.then_some(usize::MAX);
+        }
+        // stepping sideways onto the middle of a character goes on over it
+        if moved && pos.row == at.row {
+            let line = self.doc().get_line(pos.row as usize).unwrap_or_default();
+            let mut col = (pos.col as usize).min(line.len());
+            while !line.is_char_boundary(col) {
+                col = if pos.col > at.col { col + 1 } else { col - 1 };
+            }
+            self.jump_to(pos.row as usize, col);
+        }
+        if self.mode == AppMode::Normal {
+            self.clamp_to_last_char();
+        }
+        self.keys.register = keys.register;
+        // moving about in Insert mode starts the insert afresh, as far as Ctrl-w and Ctrl-u go
+        if self.mode == AppMode::Insert && (!inserting || navigated) {
+            self.insert_start = self.doc_pos();
+        }
+        if let Some(content) = self.registers.take_clipboard_write() {
+            if let Err(err) = self.clipboard.copy(&content) {
+                self.report(Err(err.into()));
+            }
+        }
+        // everything done in one go in Insert mode is undone together
+        if !matches!(self.mode, AppMode::Insert | AppMode::Replace) {
+            self.buffers.iter_mut().for_each(|buf| buf.doc.commit());
+            // the cursor is left where the change was made
+            if self.buf == buf && self.doc().change_number() > changes {
+                let pos = self.doc_pos();
+                self.doc_mut().set_mark('.', pos);
+            }
+        }
+    }
+
+    fn complete_cmd(&mut self) {
+        // a lone candidate (e.g. a directory) is completed afresh so that one can drill down
+        let cycling = self.completion.as_mut().filter(|c| c.candidates.len() > 1);
+        if let Some(completion) = cycling {
+            completion.index = (completion.index + 1) % completion.candidates.len();
+        } else {
+            let (_, cmd) = Range::parse(&self.cmd);
+            let (word, candidates) = match cmd.split_once(' ') {
+                None => {
+                    let candidates = command::COMMANDS
+                        .iter()
+                        .filter(|name| name.starts_with(cmd))
+                        .map(ToString::to_string)
+                        .collect();
+                    (cmd, candidates)
+                }
+                Some((name, arg)) if command::takes_path(name) => {
+                    let arg = arg.trim_start();
+                    (arg, complete_path(arg))
+                }
+                Some(_) => return,
+            };
+            if candidates.is_empty() {
+                self.completion = None;
+                return;
+            }
+            let base = self.cmd[..self.cmd.len() - word.len()].to_string();
+            self.completion = Some(Completion {
+                base,
+                candidates,
+                index: 0,
+            });
+        }
+
+        let completion = self.completion.as_ref().unwrap();
+        self.cmd = format!(
+            "{}{}",
+            completion.base, completion.candidates[completion.index]
+        );
+        self.cmd_cursor = self.cmd.len();
+    }
+
+    fn process_cmd(&mut self) -> CmdResult {
+        let line = self.cmd.trim_start().to_string();
+        let (range, line) = Range::parse(&line);
+        let line = self.expand_alias(line)?;
+        // trailing spaces only matter to `:normal`, which types them
+        let cmd = line.trim_end();
+        if let Some(shell_cmd) = cmd.strip_prefix('!') {
+            return match range {
+                Some(range) => self.filter_lines(range, shell_cmd),
+                None => {
+                    self.shell_cmd = Some(shell_cmd.to_string());
+                    Ok(None)
+                }
+            };
+        }
+        // `:r!cmd` is `:r !cmd`
+        if let Some(shell_cmd) = cmd.strip_prefix("r!").or(cmd.strip_prefix("read!")) {
+            return self.read_lines(range, &format!("!{}", shell_cmd));
+        }
+        if let Some(global) = Global::parse(cmd) {
+            return self.global(range.unwrap_or(Range::all()), global);
+        }
+        if let Some(body) = command::substitute_body(cmd) {
+            return self.substitute(range.unwrap_or(Range::current()), body);
+        }
+        // the name ends where its argument begins, with or without a space, e.g. `:m$`
+        let split = (cmd.find(|ch: char| !ch.is_alphabetic() && ch != '!')).unwrap_or(cmd.len());
+        let (name, arg) = (&cmd[..split], cmd[split..].trim());
+        match name {
+            "q" | "quit" | "exit" => {
+                if self.windows.len() == 1 && self.doc().dirty() {
+                    return Err(CmdError::Unsaved);
+                }
+                self.check_saved_before_quit()?;
+                self.quit_window();
+            }
+            "q!" | "quit!" => self.quit_window(),
+            "qa" | "qall" | "quitall" => {
+                self.check_all_saved()?;
+                self.running = false;
+            }
+            "qa!" | "qall!" | "quitall!" => self.running = false,
+            "h" | "help" => self.popup = Some(Popup::Help { scroll: 0 }),
+            "noh" | "nohlsearch" => self.search.highlight = false,
+            "set" => {
+                if arg.is_empty() {
+                    return Ok(Some(self.options.changed().join("  ")));
+                }
+                let mut shown = Vec::new();
+                for arg in arg.split_whitespace() {
+                    shown.extend(self.options.set(arg)?);
+                }
+                // e.g. the gutter may have pushed the cursor off-screen
+                self.jump_to(self.cursor_row(), self.cursor_col());
+                if !shown.is_empty() {
+                    return Ok(Some(shown.join("  ")));
+                }
+            }
+            "w" | "write" | "w!" | "write!" => {
+                if !arg.is_empty() {
+                    self.doc_mut().set_uri(expand_tilde(arg));
+                }
+                return self.save_doc(name.ends_with('!'));
+            }
+            "wq" | "wq!" => {
+                self.save_doc(name.ends_with('!'))?;
+                self.check_saved_before_quit()?;
+                self.quit_window();
+            }
+            "wa" | "wall" => return self.save_all(),
+            "wqa" | "wqall" | "xa" | "xall" => {
+                self.save_all()?;
+                self.running = false;
+            }
+            "x" | "xit" => {
+                if self.doc().dirty() {
+                    self.save_doc(false)?;
+                }
+                self.check_saved_before_quit()?;
+                self.quit_window();
+            }
+            "e" | "edit" | "e!" | "edit!" => {
+                if !name.ends_with('!') && self.doc().dirty() {
+                    return Err(CmdError::Unsaved);
+                }
+                if !arg.is_empty() {
+                    self.open_doc(expand_tilde(arg))?;
+                } else if let Some(uri) = self.doc().uri().map(PathBuf::from) {
+                    self.reload_doc(uri)?;
+                } else {
+                    return Err(CmdError::NoFileName);
+                }
+            }
+            "sp" | "split" | "vs" | "vsp" | "vsplit" => {
+                if name.starts_with('v') {
+                    self.split_window(Direction::Horizontal);
+                } else {
+                    self.split_window(Direction::Vertical);
+                }
+                if !arg.is_empty() {
+                    self.open_doc(expand_tilde(arg))?;
+                }
+            }
+            "f" | "file" => return Ok(Some(self.file_info())),
+            "pwd" => {
+                let dir = env::current_dir().map_err(CmdError::ChangeDir)?;
+                return Ok(Some(dir.display().to_string()));
+            }
+            "cd" => {
+                let dir = match arg {
+                    "" => expand_tilde("~"),
+                    "-" => self.prev_dir.clone().ok_or(CmdError::NoPrevDir)?,
+                    _ => expand_tilde(arg),
+                };
+                self.change_dir(dir)?;
+                let dir = env::current_dir().map_err(CmdError::ChangeDir)?;
+                return Ok(Some(dir.display().to_string()));
+            }
+            "syn" | "syntax" => match arg {
+                "on" | "enable" => {
+                    self.syntax_on = true;
+                    if syntax::detect(self.doc().uri()).is_none() {
+                        return Err(CmdError::NoSyntax);
+                    }
+                }
+                "off" => self.syntax_on = false,
+                "" => {
+                    let state = if self.syntax_on { "on" } else { "off" };
+                    let name = syntax::detect(self.doc().uri()).map_or("none", |syn| syn.name);
+                    return Ok(Some(format!("syntax {} ({})", state, name)));
+                }
+                _ => return Err(CmdError::InvalidArg(arg.to_string())),
+            },
+            "com" | "command" => {
+                if arg.is_empty() {
+                    if self.aliases.is_empty() {
+                        return Ok(Some("No aliases defined".to_string()));
+                    }
+                    let aliases: Vec<_> = (self.aliases.iter())
+                        .map(|(alias, expansion)| format!("{} => {}", alias, expansion))
+                        .collect();
+                    return Ok(Some(aliases.join("  ")));
+                }
+                self.define_alias(arg)?;
+            }
+            "ls" | "buffers" => self.popup = Some(Popup::Buffers { number: None }),
+            "k" | "mark" => {
+                let mut chars = arg.chars();
+                let mark = match (chars.next(), chars.next()) {
+                    (Some(mark), None) if mark.is_ascii_lowercase() => mark,
+                    _ => return Err(CmdError::InvalidArg(arg.to_string())),
+                };
+                let pos = self.doc_pos();
+                self.doc_mut().set_mark(mark, pos);
+            }
+            "marks" => self.popup = Some(Popup::Marks),
+            "bn" | "bnext" => {
+                let ind = (self.buf + 1) % self.buffers.len();
+                self.switch_buffer(ind);
+            }
+            "bp" | "bprev" | "bprevious" | "bN" | "bNext" => {
+                let ind = (self.buf + self.buffers.len() - 1) % self.buffers.len();
+                self.switch_buffer(ind);
+            }
+            "bd" | "bdelete" | "bd!" | "bdelete!" => {
+                let ind = if arg.is_empty() {
+                    self.buf
+                } else {
+                    let id = (arg.parse()).map_err(|_| CmdError::InvalidArg(arg.to_string()))?;
+                    (self.buffers.iter())
+                        .position(|buf| buf.id == id)
+                        .ok_or(CmdError::NoSuchBuffer(id))?
+                };
+                if !name.ends_with('!') && self.buffers[ind].doc.dirty() {
+                    return Err(CmdError::Unsaved);
+                }
+                self.close_buffer(ind);
+            }
+            "d" | "delete" => {
+                let register = register_arg(arg)?;
+                let range = range.unwrap_or(Range::current());
+                let (start, end) = range.resolve(self.cursor_row(), self.last_row());
+                let lines = self.doc_mut().delete_lines(start, end);
+                let lines = lines.iter().map(|ln| ln.as_str().to_string()).collect();
+                self.registers.delete(register, Register::Linewise(lines));
+                self.jump_to(cmp::min(start, self.last_row()), self.cursor_col());
+                if end > start {
+                    return Ok(Some(format!("{} fewer lines", end - start + 1)));
+                }
+            }
+            "u" | "undo" | "ea" | "earlier" => {
+                let count = count_arg(arg)?;
+                let (undone, cursor) = self.doc_mut().undo(count);
+                return Ok(Some(self.undo_result(undone, cursor, true)));
+            }
+            "red" | "redo" | "lat" | "later" => {
+                let count = count_arg(arg)?;
+                let (redone, cursor) = self.doc_mut().redo(count);
+                return Ok(Some(self.undo_result(redone, cursor, false)));
+            }
+            "norm" | "normal" | "norm!" | "normal!" => {
+                // the keys are all that follows one space after the name, as typed
+                let keys = &line[split..];
+                let keys = parse_keys(keys.strip_prefix(' ').unwrap_or(keys));
+                let Some(range) = range else {
+                    self.feed_keys(&keys);
+                    return Ok(None);
+                };
+                let (start, end) = range.resolve(self.cursor_row(), self.last_row());
+                for row in start..=end {
+                    if row > self.last_row() || !self.running {
+                        break;
+                    }
+                    self.jump_to(row, 0);
+                    self.feed_keys(&keys);
+                }
+            }
+            "y" | "yank" => {
+                let register = register_arg(arg)?;
+                let range = range.unwrap_or(Range::current());
+                let (start, end) = range.resolve(self.cursor_row(), self.last_row());
+                let lines = (start..=end)
+                    .map(|row| self.doc().get_line(row).unwrap_or_default().to_string())
+                    .collect();
+                self.registers.yank(register, Register::Linewise(lines));
+                if end > start {
+                    return Ok(Some(format!("{} lines yanked", end - start + 1)));
+                }
+            }
+            "reg" | "registers" | "di" | "display" => {
+                let names = arg.chars().filter(|ch| !ch.is_whitespace()).collect();
+                self.popup = Some(Popup::Registers(names));
+            }
+            "r" | "read" => return self.read_lines(range, arg),
+            "retab" | "retab!" => {
+                if !arg.is_empty() {
+                    self.options.set(&format!("tabstop={}", arg))?;
+                }
+                let range = range.unwrap_or(Range::all());
+                let (start, end) = range.resolve(self.cursor_row(), self.last_row());
+                let tabstop = self.options.tabstop as usize;
+                let expandtab = self.options.expandtab;
+                let all = name.ends_with('!');
+
+                // the character under the cursor keeps its screen column
+                let row = self.cursor_row();
+                let display = self.doc().display_col(row, self.cursor_col(), tabstop);
+                let doc = self.doc_mut();
+                let changed = (start..=end)
+                    .filter(|row| doc.retab_line(*row, tabstop, expandtab, all))
+                    .count();
+                let col = self.doc().col_at_display(row, display, tabstop);
+                self.jump_to(row, col);
+                if changed > 0 {
+                    return Ok(Some(format!("{} lines retabbed", changed)));
+                }
+            }
+            "t" | "co" | "copy" => {
+                let range = range.unwrap_or(Range::current());
+                let (start, end) = range.resolve(self.cursor_row(), self.last_row());
+                let dest = Address::parse_only(arg)?.below(self.cursor_row(), self.last_row());
+                self.doc_mut().copy_lines(start, end, dest);
+                self.jump_to(dest + end - start, self.cursor_col());
+                if end > start {
+                    return Ok(Some(format!("{} more lines", end - start + 1)));
+                }
+            }
+            "m" | "move" => {
+                let range = range.unwrap_or(Range::current());
+                let (start, end) = range.resolve(self.cursor_row(), self.last_row());
+                let dest = Address::parse_only(arg)?.below(self.cursor_row(), self.last_row());
+                if dest > start && dest <= end {
+                    return Err(CmdError::MoveIntoItself);
+                }
+                self.doc_mut().move_lines(start, end, dest);
+                let last_moved = if dest > end {
+                    dest - 1
+                } else {
+                    dest + end - start
+                };
+                self.jump_to(last_moved, self.cursor_col());
+                if end > start {
+                    return Ok(Some(format!("{} lines moved", end - start + 1)));
+                }
+            }
+            "" => {
+                if let Some(range) = range {
+                    let (_, row) = range.resolve(self.cursor_row(), self.last_row());
+                    self.jump_to(row, self.cursor_col());
+                }
+            }
+            _ => return Err(CmdError::NotACommand(cmd.to_string())),
+        }
+        Ok(None)
+    }
+
+    /// The file name, whether it is modified, and how far into it the cursor is.
+    fn file_info(&self) -> String {
+        let buffer = &self.buffers[self.buf];
+        let dirty = if buffer.doc.dirty() {
+            " [Modified]"
+        } else {
+            ""
+        };
+        let count = buffer.doc.line_count();
+        let position = if count == 0 {
+            "--No lines in buffer--".to_string()
+        } else {
+            let line = cmp::min(self.cursor_row() + 1, count);
+            format!("line {} of {} --{}%--", line, count, line * 100 / count)
+        };
+        format!("\"{}\"{} {}", buffer.name(), dirty, position)
+    }
+
+    /// Make `dir` the working directory, remembering the one left for `:cd -`.
+    fn change_dir(&mut self, dir: PathBuf) -> Result<(), CmdError> {
+        let current = env::current_dir().map_err(CmdError::ChangeDir)?;
+        env::set_current_dir(&dir).map_err(CmdError::ChangeDir)?;
+        // open buffers keep pointing at the same files
+        for buffer in self.buffers.iter_mut() {
+            if let Some(uri) = buffer.doc.uri().filter(|uri| uri.is_relative()) {
+                let uri = current.join(uri);
+                buffer.doc.set_uri(uri);
+            }
+        }
+        self.prev_dir = Some(current);
+        Ok(())
+    }
+
+    /// Act on `keys` as if they were typed in Normal mode, returning to it afterwards.
+    fn feed_keys(&mut self, keys: &[KeyEvent]) {
+        self.mode = AppMode::Normal;
+        // the keys a macro still has queued up wait until these are done
+        let queued = mem::replace(&mut self.queue, keys.iter().copied().collect());
+        while let Some(key) = self.queue.pop_front() {
+            match self.handle_event(Event::Key(key)) {
+                Ok(action) => self.process(action),
+                Err(err) => {
+                    warn!("{}", err);
+                    break;
+                }
+            }
+            if !self.running {
+                return;
+            }
+        }
+        self.queue = queued;
+
+        // whatever is left unfinished is dropped
+        self.keys.pending = None;
+        self.keys.count = None;
+        self.confirm = None;
+        if self.mode != AppMode::Normal {
+            self.process(AppAction::EnterMode(AppMode::Normal));
+        }
+    }
+
+    /// Put the cursor back where a run of undone or redone changes was made, and describe it.
+    fn undo_result(&mut self, count: usize, cursor: Option<Position>, undo: bool) -> String {
+        let Some(cursor) = cursor else {
+            let end = if undo { "oldest" } else { "newest" };
+            return format!("Already at {} change", end);
+        };
+        self.jump_to(cursor.row as usize, cursor.col as usize);
+        let changes = if count == 1 { "change" } else { "changes" };
+        let number = self.doc().change_number();
+        if undo {
+            format!("{} {}; before #{}", count, changes, number + 1)
+        } else {
+            format!("{} {}; after #{}", count, changes, number)
+        }
+    }
+
+    /// Expand `cmd` for as long as it starts with an alias, passing its argument along.
+    fn expand_alias(&self, cmd: &str) -> Result<String, CmdError> {
+        let mut cmd = cmd.to_string();
+        let mut seen = Vec::new();
+        loop {
+            let (name, arg) = cmd.split_once(' ').unwrap_or((&cmd, ""));
+            let Some(expansion) = self.aliases.get(name) else {
+                return Ok(cmd);
+            };
+            if seen.iter().any(|alias| alias == name) {
+                return Err(CmdError::RecursiveAlias(name.to_string()));
+            }
+            seen.push(name.to_string());
+            cmd = if arg.is_empty() {
+                expansion.clone()
+            } else {
+                format!("{} {}", expansion, arg)
+            };
+        }
+    }
+
+    /// Define an alias from `:command <alias> <expansion>`, refusing one that would expand into
+    /// itself.
+    fn define_alias(&mut self, arg: &str) -> Result<(), CmdError> {
+        let (alias, expansion) = match arg.split_once(' ') {
+            Some((alias, expansion)) if !expansion.trim().is_empty() => (alias, expansion.trim()),
+            _ => return Err(CmdError::InvalidArg(arg.to_string())),
+        };
+        let previous = self
+            .aliases
+            .insert(alias.to_string(), expansion.to_string());
+        if let Err(err) = self.expand_alias(alias) {
+            match previous {
+                Some(previous) => self.aliases.insert(alias.to_string(), previous),
+                None => self.aliases.remove(alias),
+            };
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Insert the content of file `arg`, or the output of shell command `!cmd`, below `range`.
+    fn read_lines(&mut self, range: Option<Range>, arg: &str) -> CmdResult {
+        let mut note = None;
+        let (content, source) = if let Some(cmd) = arg.strip_prefix('!') {
+            let output = shell::capture(cmd).map_err(CmdError::Shell)?;
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            note = (stderr.lines())
+                .rfind(|ln| !ln.trim().is_empty())
+                .map(ToString::to_string);
+            if !output.status.success() {
+                return Err(match note {
+                    Some(note) => CmdError::ShellOutput(note),
+                    None => CmdError::ShellStatus(output.status),
+                });
+            }
+            let content = String::from_utf8_lossy(&output.stdout).into_owned();
+            (content, format!("`{}`", cmd))
+        } else if !arg.is_empty() {
+            let content = fs::read_to_string(expand_tilde(arg)).map_err(CmdError::Open)?;
+            (content, format!("\"{}\"", arg))
+        } else {
+            return Err(CmdError::NoFileName);
+        };
+
+        let lines: Vec<_> = content.lines().map(DocLine::from_str).collect();
+        if lines.is_empty() {
+            return Ok(Some(format!("{} is empty, nothing read", source)));
+        }
+
+        // `:0r` reads above the first line, `:Nr` below line N
+        let row = match range {
+            Some(range) => range.end.below(self.cursor_row(), self.last_row()),
+            None => self.cursor_row() + 1,
+        };
+        let count = lines.len();
+        self.doc_mut().insert_lines(row, lines);
+        self.jump_to(cmp::min(row, self.last_row()), 0);
+        match note {
+            Some(note) => Ok(Some(note)),
+            None => Ok(Some(format!("{} {} lines read", source, count))),
+        }
+    }
+
+    /// Pipe the lines in `range` through `cmd`, replacing them with what it prints.
+    fn filter_lines(&mut self, range: Range, cmd: &str) -> CmdResult {
+        let (start, end) = range.resolve(self.cursor_row(), self.last_row());
+        let mut input = String::new();
+        for row in start..=end {
+            input.push_str(self.doc().get_line(row).unwrap_or_default());
+            input.push('\n');
+        }
+
+        let output = shell::filter(cmd, input).map_err(CmdError::Shell)?;
+        if !output.status.success() {
+            return Err(CmdError::ShellStatus(output.status));
+        }
+        let output = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<_> = output.lines().map(DocLine::from_str).collect();
+        let count = lines.len();
+        self.doc_mut().replace_lines(start, end, lines);
+        self.jump_to(cmp::min(start, self.last_row()), self.cursor_col());
+        Ok(Some(format!("{} lines filtered", count)))
+    }
+
+    fn substitute(&mut self, range: Range, body: &str) -> CmdResult {
+        let mut sub = Substitute::parse(body)?;
+        if sub.pattern.is_empty() {
+            sub.pattern = self.search.pattern.clone().ok_or(CmdError::NoPrevPattern)?;
+        }
+        self.search.pattern = Some(sub.pattern.clone());
+        self.search.highlight = true;
+
+        let (start, end) = range.resolve(self.cursor_row(), self.last_row());
+        if sub.confirm {
+            self.confirm = Some(SubConfirm {
+                sub,
+                row: start,
+                col: 0,
+                end,
+                subs: 0,
+                lines: 0,
+                last_sub_row: None,
+            });
+            self.mode = AppMode::Confirm;
+            self.next_confirm();
+            return Ok(None);
+        }
+
+        let (subs, lines) =
+            self.doc_mut()
+                .replace_in_range(start, end, &sub.pattern, &sub.replacement, sub.global);
+        (self.cursor, self.view_shift) = self.clamp_to_doc(self.cursor, self.view_shift);
+        substitution_result(&sub.pattern, subs, lines)
+    }
+
+    /// Run the command of a `:g` on each of its lines, which are all picked out before any is
+    /// changed.
+    fn global(&mut self, range: Range, mut global: Global) -> CmdResult {
+        if global.pattern.is_empty() {
+            global.pattern = self.search.pattern.clone().ok_or(CmdError::NoPrevPattern)?;
+        }
+        self.search.pattern = Some(global.pattern.clone());
+        self.search.highlight = true;
+
+        let (start, end) = range.resolve(self.cursor_row(), self.last_row());
+        let rows: Vec<_> = (start..=end)
+            .filter(|row| {
+                let line = self.doc().get_line(*row).unwrap_or_default();
+                line.contains(&global.pattern) != global.invert
+            })
+            .collect();
+        if rows.is_empty() {
+            return Err(CmdError::PatternNotFound(global.pattern));
+        }
+
+        let command = global.command.as_str();
+        if command.is_empty() {
+            return Ok(Some(format!("{} matching lines", rows.len())));
+        }
+        if command == "d" || command == "delete" {
+            // bottom up, so that each deletion leaves the rows still to go where they were
+            for row in rows.iter().rev() {
+                self.doc_mut().delete_line(*row);
+            }
+            self.jump_to(cmp::min(rows[0], self.last_row()), 0);
+            return Ok(Some(format!("{} fewer lines", rows.len())));
+        }
+        let Some(body) = command::substitute_body(command) else {
+            return Err(CmdError::NotACommand(command.to_string()));
+        };
+
+        let mut sub = Substitute::parse(body)?;
+        if sub.confirm {
+            return Err(CmdError::Parse(CommandError::InvalidFlag('c')));
+        }
+        if sub.pattern.is_empty() {
+            sub.pattern = global.pattern;
+        }
+        let mut subs = 0;
+        let mut lines = 0;
+        let doc = self.doc_mut();
+        for row in rows {
+            let count = doc.replace_in_line(row, &sub.pattern, &sub.replacement, sub.global);
+            if count > 0 {
+                subs += count;
+                lines += 1;
+            }
+        }
+        (self.cursor, self.view_shift) = self.clamp_to_doc(self.cursor, self.view_shift);
+        substitution_result(&sub.pattern, subs, lines)
+    }
+
+    /// Park the cursor on the next match pending confirmation, or finish the substitution.
+    fn next_confirm(&mut self) {
+        let Some(confirm) = self.confirm.as_mut() else {
+            return;
+        };
+        if confirm.seek(&self.buffers[self.buf].doc) {
+            let (row, col) = (confirm.row, confirm.col);
+            self.jump_to(row, col);
+            return;
+        }
+
+        let confirm = self.confirm.take().unwrap();
+        self.mode = AppMode::Normal;
+        self.report(substitution_result(
+            &confirm.sub.pattern,
+            confirm.subs,
+            confirm.lines,
+        ));
+        (self.cursor, self.view_shift) = self.clamp_to_doc(self.cursor, self.view_shift);
+    }
+
+    fn answer_confirm(&mut self, answer: ConfirmAnswer) {
+        let Some(confirm) = self.confirm.as_mut() else {
+            return;
+        };
+        let doc = &mut self.buffers[self.buf].doc;
+        match answer {
+            ConfirmAnswer::Yes => confirm.replace(doc),
+            ConfirmAnswer::No => confirm.skip(),
+            ConfirmAnswer::All => {
+                confirm.replace(doc);
+                while confirm.seek(doc) {
+                    confirm.replace(doc);
+                }
+            }
+            ConfirmAnswer::Quit => confirm.row = confirm.end + 1,
+        }
+        self.next_confirm();
+    }
+
+    /// Search for the pattern typed after `/`, or `?` going `backward`, or for the last one if
+    /// none was.
+    fn search(&mut self, backward: bool) -> CmdResult {
+        if !self.cmd.is_empty() {
+            self.search.pattern = Some(self.cmd.clone());
+        }
+        let pattern = self.search.pattern.clone().ok_or(CmdError::NoPrevPattern)?;
+        self.search.highlight = true;
+        self.search.backward = backward;
+        self.find_pattern(&pattern, backward, 1)
+    }
+
+    /// Search for the last pattern again `count` times, the other way round with `reverse`.
+    fn search_next(&mut self, reverse: bool, count: usize) -> CmdResult {
+        let pattern = self.search.pattern.clone().ok_or(CmdError::NoPrevPattern)?;
+        self.search.highlight = true;
+        self.find_pattern(&pattern, self.search.backward ^ reverse, count)
+    }
+
+    /// Move the cursor to the `count`th match of `pattern` on, or back going `backward`, noting
+    /// if the search went past the end.
+    fn find_pattern(&mut self, pattern: &str, backward: bool, count: usize) -> CmdResult {
+        let from = self.doc_pos();
+        let (mut to, mut wrapped) = (from, false);
+        for _ in 0..count.max(1) {
+            let (next, wraps) = (self.doc().find(pattern, to, backward))
+                .ok_or_else(|| CmdError::PatternNotFound(pattern.to_string()))?;
+            (to, wrapped) = (next, wrapped || wraps);
+        }
+        self.doc_mut().set_mark('`', from);
+        self.jump_to(to.row as usize, to.col as usize);
+        if !wrapped {
+            return Ok(None);
+        }
+        let wrapped = match backward {
+            false => "search hit BOTTOM, continuing at TOP",
+            true => "search hit TOP, continuing at BOTTOM",
+        };
+        Ok(Some(wrapped.to_string()))
+    }
+
+    /// Show the outcome of a command in the message area.
+    fn report(&mut self, result: CmdResult) {
+        match result {
+            Ok(Some(info)) => self.message = Some((info, Severity::Info)),
+            Ok(None) => {}
+            Err(err) => {
+                self.message = Some((err.to_string(), Severity::Error));
+                self.fail();
+            }
+        }
+    }
+
+    /// Drop the rest of the macro being replayed after a command failed, so that it goes no
+    /// further.
+    fn fail(&mut self) {
+        self.queue.clear();
+    }
+
+    /// What the status line shows before the text being typed into it, if anything is.
+    fn prompt(&self) -> Option<&'static str> {
+        match self.mode {
+            AppMode::Command => Some(CMD_PROMPT),
+            AppMode::Search { backward: false } => Some("/"),
+            AppMode::Search { backward: true } => Some("?"),
+            _ => None,
+        }
+    }
+
+    /// Width of the line-number gutter left of the document, `0` when it is hidden.
+    fn gutter_width(&self) -> u16 {
+        self.doc_gutter_width(self.doc())
+    }
+
+    fn doc_gutter_width(&self, doc: &Document) -> u16 {
+        if !self.options.number {
+            return 0;
+        }
+        let digits = doc.line_count().to_string().len();
+        cmp::max(digits, 3) as u16 + 1
+    }
+
+    /// Where each window is drawn, in the same order as `self.windows`, and where the separators
+    /// between side-by-side windows go.
+    fn layout(&self) -> (Vec<Rect>, Vec<Rect>) {
+        let [main_area, _] = vertical![*=1, ==1].areas(self.size);
+        let mut windows = Vec::new();
+        let mut separators = Vec::new();
+        self.splits.layout(main_area, &mut windows, &mut separators);
+        windows.sort_by_key(|(ind, _)| *ind);
+        let windows = windows.into_iter().map(|(_, area)| area).collect();
+        (windows, separators)
+    }
+
+    fn window_areas(&self) -> Vec<Rect> {
+        self.layout().0
+    }
+
+    /// The window next to the focused one in direction `mv`, preferring the one lined up with the
+    /// cursor.
+    fn window_towards(&self, mv: Move) -> Option<usize> {
+        let areas = self.window_areas();
+        let from = areas[self.win];
+        let area = self.area();
+        let x = area.x + self.gutter_width() + self.cursor.col;
+        let y = area.y + self.cursor.row;
+        // how far `pos` is from lying within `start..end`
+        let off = |pos: u16, start: u16, end: u16| {
+            start.saturating_sub(pos) + (pos + 1).saturating_sub(end)
+        };
+
+        (areas.iter().enumerate())
+            .filter_map(|(ind, to)| {
+                let key = match mv {
+                    Move::Left if to.right() <= from.x => {
+                        (from.x - to.right(), off(y, to.y, to.bottom()))
+                    }
+                    Move::Right if to.x >= from.right() => {
+                        (to.x - from.right(), off(y, to.y, to.bottom()))
+                    }
+                    Move::Up if to.bottom() <= from.y => {
+                        (from.y - to.bottom(), off(x, to.x, to.right()))
+                    }
+                    Move::Down if to.y >= from.bottom() => {
+                        (to.y - from.bottom(), off(x, to.x, to.right()))
+                    }
+                    _ => return None,
+                };
+                Some((key, ind))
+            })
+            .min()
+            .map(|(_, ind)| ind)
+    }
+
+    /// The part of a window's `area` showing its document, above the window's own status line.
+    fn text_area(&self, area: Rect) -> Rect {
+        if self.windows.len() > 1 {
+            Rect {
+                height: area.height.saturating_sub(1),
+                ..area
+            }
+        } else {
+            area
+        }
+    }
+
+    /// Where the focused window shows its document.
+    fn area(&self) -> Rect {
+        self.text_area(self.window_areas()[self.win])
+    }
+
+    /// The cursor's position in the document rather than on screen.
+    #[inline]
+    fn doc_pos(&self) -> Position {
+        Position {
+            row: self.view_shift.row.saturating_add(self.cursor.row),
+            col: self.view_shift.col.saturating_add(self.cursor.col),
+        }
+    }
+
+    #[inline]
+    fn cursor_row(&self) -> usize {
+        self.view_shift.row as usize + self.cursor.row as usize
+    }
+
+    #[inline]
+    fn cursor_col(&self) -> usize {
+        self.view_shift.col as usize + self.cursor.col as usize
+    }
+
+    #[inline]
+    fn last_row(&self) -> usize {
+        self.doc().line_count().saturating_sub(1)
+    }
+
+    /// Place the cursor on the given document position, scrolling the view when it is off-screen.
+    fn jump_to(&mut self, row: usize, col: usize) {
+        let area = self.area();
+        let height = area.height as usize;
+        let width = area.width.saturating_sub(self.gutter_width()) as usize;
+
+        let mut view_shift = self.view_shift;
+        if row < view_shift.row as usize || row >= view_shift.row as usize + height {
+            view_shift.row = row.saturating_sub(height / 2) as u16;
+        }
+        if col < view_shift.col as usize || col >= view_shift.col as usize + width {
+            view_shift.col = (col + 1).saturating_sub(width) as u16;
+        }
+
+        let cursor = Position {
+            row: (row - view_shift.row as usize) as u16,
+            col: (col - view_shift.col as usize) as u16,
+        };
+        (self.cursor, self.view_shift) = self.clamp_to_doc(cursor, view_shift);
+    }
+
+    /// Scroll the view `lines` down or up, moving the cursor as many lines along. Near either end
+    /// of the document, where the view cannot go as far, the cursor still does.
+    fn scroll_with_cursor(&mut self, down: bool, lines: usize) {
+        let (top, row, last) = (
+            self.view_shift.row as usize,
+            self.cursor_row(),
+            self.last_row(),
+        );
+        if (down && row == last) || (!down && row == 0) {
+            return self.fail();
+        }
+        let (top, to) = match down {
+            true => {
+                // stopping once the last line is at the bottom of the window
+                let bottom_top = (last + 1).saturating_sub(self.area().height as usize);
+                let top = (top + lines).min(bottom_top.max(top));
+                (top, (row + lines).min(last))
+            }
+            false => (top.saturating_sub(lines), row.saturating_sub(lines)),
+        };
+        self.view_shift.row = top as u16;
+        self.jump_to(to, self.cursor_col());
+        self.clamp_to_last_char();
+    }
+
+    /// Scroll the view `count` windows down or up, less two lines each so as to keep some in
+    /// sight, and bring the cursor along to the first or last line in view. Once the view cannot
+    /// go further the cursor goes to the first or last line of the document.
+    fn page(&mut self, down: bool, count: usize) {
+        let (top, row, last) = (
+            self.view_shift.row as usize,
+            self.cursor_row(),
+            self.last_row(),
+        );
+        let height = self.area().height as usize;
+        let lines = count.max(1) * height.saturating_sub(2).max(1);
+        let bottom_top = (last + 1).saturating_sub(height);
+        let new_top = match down {
+            true => (top + lines).min(bottom_top.max(top)),
+            false => top.saturating_sub(lines),
+        };
+        let to = match (down, new_top == top) {
+            (true, true) => last,
+            (false, true) => 0,
+            (true, false) => row.max(new_top),
+            (false, false) => row.min(new_top + height - 1),
+        };
+        if to == row && new_top == top {
+            return self.fail();
+        }
+        self.view_shift.row = new_top as u16;
+        let past_end = matches!(self.mode, AppMode::Insert | AppMode::Replace);
+        self.jump_to(to, self.col_on_line(to, self.cursor_col(), past_end));
+    }
+
+    /// Scroll the view for the cursor line to be at `at` in the window, `scrolloff` lines in from
+    /// its edge, without going further than the last line needs to be in view.
+    fn place_view(&mut self, at: ScreenRow) {
+        let (row, height) = (self.cursor_row(), self.area().height as usize);
+        let scrolloff = (self.options.scrolloff as usize).min(height.saturating_sub(1) / 2);
+        let top = match at {
+            ScreenRow::Top => row.saturating_sub(scrolloff),
+            ScreenRow::Middle => row.saturating_sub(height.saturating_sub(1) / 2),
+            ScreenRow::Bottom => (row + 1 + scrolloff).saturating_sub(height),
+        };
+        let bottom_top = (self.last_row() + 1).saturating_sub(height);
+        self.view_shift.row = top.min(bottom_top) as u16;
+        self.jump_to(row, self.cursor_col());
+    }
+
+    /// Move the cursor where `motion` leads, leaving the `` ` `` mark behind if it jumps.
+    fn move_by(&mut self, motion: Motion, count: usize) {
+        let from = self.doc_pos();
+        let Some(to) = motion.target(self.doc(), from, count) else {
+            return self.fail();
+        };
+        if motion.is_jump() {
+            self.doc_mut().set_mark('`', from);
+        }
+        // a line jumped to from afar is shown at the edge of the view it comes in from
+        let (row, height) = (to.row as usize, self.area().height as usize);
+        if row < self.view_shift.row as usize {
+            self.view_shift.row = to.row;
+        } else if row >= self.view_shift.row as usize + height {
+            self.view_shift.row = (row + 1 - height) as u16;
+        }
+        // in Insert mode the end of the line is just past its last character
+        match self.mode {
+            AppMode::Insert | AppMode::Replace if motion == Motion::LineEnd => {
+                self.jump_to(row, self.doc().get_line_len(row));
+            }
+            AppMode::Insert | AppMode::Replace => self.jump_to(row, to.col as usize),
+            _ => {
+                self.jump_to(row, to.col as usize);
+                self.clamp_to_last_char();
+            }
+        }
+    }
+
+    /// Apply `op` to the text between the cursor and where `motion` leads.
+    fn operate(&mut self, op: Operator, mut motion: Motion, mut count: usize) {
+        let from = self.doc_pos();
+        // `cw` on a word changes up to its end, leaving the blanks after it alone
+        let line = self.doc().get_line(from.row as usize).unwrap_or_default();
+        let mut chars = line.get(from.col as usize..).unwrap_or_default().chars();
+        let (under, after) = (chars.next(), chars.next());
+        let class = under
+            .map(CharClass::of)
+            .filter(|class| *class != CharClass::Blank);
+        if let (Operator::Change, Motion::WordStart, Some(class)) = (op, motion, class) {
+            motion = Motion::WordEnd;
+            // on the last character of a word, that word is the first of `count` on its own
+            if after.map(CharClass::of) != Some(class) {
+                count = count.max(1) - 1;
+                if count == 0 {
+                    let end = Position {
+                        row: from.row,
+                        col: from.col + under.map_or(0, char::len_utf8) as u16,
+                    };
+                    return self.apply(op, TextRange::chars(from, end));
+                }
+            }
+        }
+
+        match motion.range(self.doc(), from, count) {
+            Some(range) => self.apply(op, range),
+            None => self.fail(),
+        }
+    }
+
+    /// Apply `op` to `count` of `object` at the cursor, or select them without one.
+    fn operate_object(&mut self, op: Option<Operator>, object: TextObject, count: usize) {
+        let at = self.doc_pos();
+        let Some(range) = object.range(self.doc(), at, count) else {
+            return self.fail();
+        };
+        match op {
+            Some(op) => self.apply(op, range),
+            None => self.select(range),
+        }
+    }
+
+    /// Apply `op` to `range`, on the lines it is on as a whole if either is linewise.
+    fn apply(&mut self, op: Operator, range: TextRange) {
+        match range.kind {
+            RangeKind::Charwise if !op.linewise() => self.operate_range(op, range.start, range.end),
+            RangeKind::Blockwise if !op.linewise() => {
+                self.operate_block(op, range.start, range.end)
+            }
+            _ => {
+                let (start, end) = range.rows();
+                self.operate_lines(op, start, end);
+            }
+        }
+    }
+
+    /// Apply `op` to the text from `start` up to but not including `end`.
+    fn operate_range(&mut self, op: Operator, start: Position, end: Position) {
+        if let Operator::Case(case) = op {
+            self.doc_mut()
+                .transform_range(start, end, |text| case.apply(text));
+            return self.jump_to(start.row as usize, start.col as usize);
+        }
+        if op == Operator::Yank {
+            let text = self.doc().get_range(start, end);
+            self.registers
+                .yank(self.keys.register, Register::Charwise(text));
+            return self.jump_to(start.row as usize, start.col as usize);
+        }
+        let removed = self.doc_mut().delete_range(start, end);
+        if !removed.is_empty() {
+            self.registers
+                .delete(self.keys.register, Register::Charwise(removed));
+        }
+        self.jump_to(start.row as usize, start.col as usize);
+        match op {
+            Operator::Change => self.mode = AppMode::Insert,
+            _ => self.clamp_to_last_char(),
+        }
+    }
+
+    /// Apply `op` to lines `start..=end` as a whole.
+    fn operate_lines(&mut self, op: Operator, start: usize, end: usize) {
+        match op {
+            Operator::Delete => {
+                let removed = self.doc_mut().delete_lines(start, end);
+                let lines = removed.iter().map(|ln| ln.as_str().to_string()).collect();
+                self.registers
+                    .delete(self.keys.register, Register::Linewise(lines));
+                let row = start.min(self.last_row());
+                self.jump_to(row, self.first_non_blank(row));
+            }
+            Operator::Change => self.change_lines(start, end),
+            Operator::Indent => self.shift_lines(start, end, 1),
+            Operator::Dedent => self.shift_lines(start, end, -1),
+            Operator::Case(case) => {
+                let end = Position {
+                    row: end as u16,
+                    col: self.doc().get_line_len(end) as u16,
+                };
+                let start = Position {
+                    row: start as u16,
+                    col: 0,
+                };
+                self.doc_mut()
+                    .transform_range(start, end, |text| case.apply(text));
+                if (start.row as usize) < self.cursor_row() {
+                    let row = start.row as usize;
+                    self.jump_to(row, self.first_non_blank(row));
+                }
+            }
+            Operator::Yank => {
+                let end = end.min(self.last_row());
+                let lines: Vec<String> = (start..=end)
+                    .filter_map(|row| self.doc().get_line(row).map(str::to_string))
+                    .collect();
+                self.message = Some((
+                    match lines.len() {
+                        1 => "1 line yanked".to_string(),
+                        count => format!("{} lines yanked", count),
+                    },
+                    Severity::Info,
+                ));
+                self.registers
+                    .yank(self.keys.register, Register::Linewise(lines));
+                // left at the start of what was yanked, as after `ygg`
+                if start < self.cursor_row() {
+                    self.jump_to(start, self.first_non_blank(start));
+                }
+            }
+        }
+    }
+
+    /// Shift lines `start..=end` by `levels` times `shiftwidth`, right or left, keeping the cursor
+    /// on its character if it is on the first of them.
+    fn shift_lines(&mut self, start: usize, end: usize, levels: isize) {
+        let (row, col) = (self.cursor_row(), self.cursor_col());
+        let indent_len = |app: &Self| {
+            let line = app.doc().get_line(row).unwrap_or_default();
+            line.len() - line.trim_start().len()
+        };
+        let old_indent = indent_len(self);
+        let options = self.options.clone();
+        let changed = self.doc_mut().indent_lines(start, end, levels, &options);
+        if changed > 1 {
+            let shifted = if levels > 0 { "indented" } else { "dedented" };
+            let info = format!("{} lines {}", changed, shifted);
+            self.message = Some((info, Severity::Info));
+        }
+
+        if row != start {
+            return self.jump_to(start, self.first_non_blank(start));
+        }
+        let new_indent = indent_len(self);
+        match col.checked_sub(old_indent) {
+            Some(past) => self.jump_to(row, new_indent + past),
+            None => self.jump_to(row, col.min(new_indent)),
+        }
+    }
+
+    /// Put the chosen register, or else the unnamed one, `count` times after the cursor, or
+    /// `before` it: whole lines go below or above the cursor line.
+    fn put(&mut self, before: bool, count: usize) {
+        let name = self.keys.register.unwrap_or('"');
+        let Some(register) = self.registers.get(name).cloned() else {
+            return;
+        };
+        let (row, col) = (self.cursor_row(), self.cursor_col());
+        match register {
+            Register::Linewise(lines) => {
+                let row = if before { row } else { row + 1 };
+                let lines = (lines.iter())
+                    .map(|ln| DocLine::from_str(ln))
+                    .cycle()
+                    .take(lines.len() * count.max(1))
+                    .collect();
+                self.doc_mut().insert_lines(row, lines);
+                let row = row.min(self.last_row());
+                self.jump_to(row, self.first_non_blank(row));
+            }
+            Register::Charwise(text) => {
+                let line = self.doc().get_line(row).unwrap_or_default();
+                let under = line.get(col..).and_then(|rest| rest.chars().next());
+                let col = match before {
+                    true => col,
+                    false => col + under.map_or(0, char::len_utf8),
+                };
+                let at = Position {
+                    row: row as u16,
+                    col: col as u16,
+                };
+                let end = self.doc_mut().insert_str(at, &text.repeat(count.max(1)));
+                // on the last character put, or at the start of text spanning lines
+                if end.row == at.row {
+                    let line = self.doc().get_line(row).unwrap_or_default();
+                    let last = line[..end.col as usize].char_indices().next_back();
+                    self.jump_to(row, last.map_or(col, |(col, _)| col));
+                } else {
+                    self.jump_to(row, col);
+                }
+            }
+            // a block goes in at the same column on the lines from the cursor on
+            Register::Blockwise(lines) => {
+                let line = self.doc().get_line(row).unwrap_or_default();
+                let under = line.get(col..).and_then(|rest| rest.chars().next());
+                let col = match before {
+                    true => col,
+                    false => col + under.map_or(0, char::len_utf8),
+                };
+                let at = Position {
+                    row: row as u16,
+                    col: col as u16,
+                };
+                let texts: Vec<_> = lines.iter().map(|ln| ln.repeat(count.max(1))).collect();
+                self.doc_mut().insert_block(at, &texts, true);
+                self.jump_to(row, col);
+            }
+        }
+    }
+
+    /// Blank lines `start..=end` into one, keeping the indentation of the first, and insert
+    /// there.
+    fn change_lines(&mut self, start: usize, end: usize) {
+        let end = end.min(self.last_row());
+        let lines: Vec<String> = (start..=end)
+            .filter_map(|row| self.doc().get_line(row).map(str::to_string))
+            .collect();
+        // an empty buffer has nothing to change, but what is typed makes its first line
+        let Some(first) = lines.first() else {
+            self.mode = AppMode::Insert;
+            return;
+        };
+        let indent = first[..first.len() - first.trim_start().len()].to_string();
+
+        self.doc_mut()
+            .replace_lines(start, end, vec![DocLine::from_str(&indent)]);
+        self.registers
+            .delete(self.keys.register, Register::Linewise(lines));
+        self.jump_to(start, indent.len());
+        self.mode = AppMode::Insert;
+    }
+
+    /// Insert an empty line at `row` and start inserting on it, indented like the cursor line
+    /// with `autoindent`.
+    fn open_line(&mut self, row: usize) {
+        let line = self.doc().get_line(self.cursor_row()).unwrap_or_default();
+        let indent = match self.options.autoindent {
+            true => &line[..line.len() - line.trim_start().len()],
+            false => "",
+        };
+        let line = DocLine::from_str(indent);
+        let col = indent.len();
+        self.doc_mut().insert_line(row, line);
+        self.jump_to(row, col);
+        self.mode = AppMode::Insert;
+    }
+
+    /// The Visual mode selection as its first and last position, whichever way it was made.
+    fn selection(&self) -> (Position, Position) {
+        let (anchor, cursor) = (self.anchor, self.doc_pos());
+        if (anchor.row, anchor.col) <= (cursor.row, cursor.col) {
+            (anchor, cursor)
+        } else {
+            (cursor, anchor)
+        }
+    }
+
+    /// The Visual block as its top left and bottom right corners, the latter just past its last
+    /// column.
+    fn block(&self) -> (Position, Position) {
+        let (anchor, cursor) = (self.anchor, self.doc_pos());
+        let right = if anchor.col > cursor.col {
+            anchor
+        } else {
+            cursor
+        };
+        let line = self.doc().get_line(right.row as usize).unwrap_or_default();
+        let under = (line.get(right.col as usize..)).and_then(|rest| rest.chars().next());
+        let start = Position {
+            row: anchor.row.min(cursor.row),
+            col: anchor.col.min(cursor.col),
+        };
+        let end = Position {
+            row: anchor.row.max(cursor.row),
+            col: right.col + under.map_or(1, char::len_utf8) as u16,
+        };
+        (start, end)
+    }
+
+    /// Apply `op` to the columns from `start`'s up to but not including `end`'s, on each of the
+    /// lines between.
+    fn operate_block(&mut self, op: Operator, start: Position, end: Position) {
+        match op {
+            Operator::Yank => {
+                let lines = self.doc().get_block(start, end);
+                self.registers
+                    .yank(self.keys.register, Register::Blockwise(lines));
+            }
+            Operator::Delete | Operator::Change => {
+                let lines = self.doc_mut().delete_block(start, end);
+                self.registers
+                    .delete(self.keys.register, Register::Blockwise(lines));
+            }
+            Operator::Indent | Operator::Dedent => unreachable!("shifting takes in whole lines"),
+            Operator::Case(case) => {
+                for row in start.row..=end.row {
+                    let (start, end) = (Position { row, ..start }, Position { row, ..end });
+                    self.doc_mut()
+                        .transform_range(start, end, |text| case.apply(text));
+                }
+            }
+        }
+        // changing goes on to insert where the block was, on each of its lines
+        if op == Operator::Change {
+            return self.insert_in_block(start, end, false);
+        }
+        self.jump_to(start.row as usize, start.col as usize);
+        self.clamp_to_last_char();
+    }
+
+    /// Start inserting on the first line of the block from `start` to `end`, before it or after it
+    /// with `append`, for the text to be copied onto the other lines when done.
+    fn insert_in_block(&mut self, start: Position, end: Position, append: bool) {
+        let row = start.row as usize;
+        let col = match append {
+            true => end.col as usize,
+            false => start.col as usize,
+        };
+        // appending goes past the end of a line shorter than the block
+        if append && self.doc().get_line_len(row) < col {
+            let at = Position {
+                row: row as u16,
+                col: col as u16,
+            };
+            self.doc_mut().insert_block(at, &[String::new()], true);
+        }
+        self.jump_to(row, col);
+        self.block_insert = Some(BlockInsert {
+            at: self.doc_pos(),
+            col,
+            end_row: end.row as usize,
+            pad: append,
+        });
+        self.mode = AppMode::Insert;
+    }
+
+    /// Copy what was typed on the first line of a Visual block onto its other lines, unless the
+    /// typing went onto another line.
+    fn finish_block_insert(&mut self, block: BlockInsert) {
+        let (row, col) = (self.cursor_row(), self.cursor_col());
+        if row != block.at.row as usize || col <= block.at.col as usize {
+            return;
+        }
+        let line = self.doc().get_line(row).unwrap_or_default();
+        let text = line[block.at.col as usize..col].to_string();
+        let at = Position {
+            row: row as u16 + 1,
+            col: block.col as u16,
+        };
+        let texts = vec![text; block.end_row.saturating_sub(row)];
+        self.doc_mut().insert_block(at, &texts, block.pad);
+    }
+
+    /// Delete back to the start of the word before the cursor, or of all the text before it
+    /// without `word`, stopping once where the insert started and then at the start of the line.
+    fn delete_back(&mut self, word: bool) {
+        let (row, col) = (self.cursor_row(), self.cursor_col());
+        let start = self.insert_start;
+        let bound = match start.row as usize == row && (start.col as usize) < col {
+            true => start.col as usize,
+            false => 0,
+        };
+        let line = self.doc().get_line(row).unwrap_or_default();
+        let Some(before) = line.get(bound..col) else {
+            return;
+        };
+        let from = Position {
+            row: row as u16,
+            col: (bound
+                + if word {
+                    motion::line_word_back(before)
+                } else {
+                    0
+                }) as u16,
+        };
+        let at = self.doc_pos();
+        self.doc_mut().delete_range(from, at);
+        self.jump_to(row, from.col as usize);
+    }
+
+    /// Have the insert starting at the cursor made `count` times over once done, each time on a
+    /// line of its own with `open`.
+    fn repeat_insert(&mut self, count: usize, open: bool) {
+        self.repeated_insert = (count > 1).then(|| RepeatedInsert {
+            at: self.doc_pos(),
+            count,
+            open,
+        });
+    }
+
+    /// Insert what was typed since the insert started again, for the count it was given, unless
+    /// the typing went back before where it started.
+    fn finish_repeated_insert(&mut self, repeat: RepeatedInsert) {
+        let end = self.doc_pos();
+        if (end.row, end.col) < (repeat.at.row, repeat.at.col) {
+            return;
+        }
+        let text = self.doc().get_range(repeat.at, end);
+        let mut at = end;
+        for _ in 1..repeat.count {
+            if repeat.open {
+                self.open_line(at.row as usize + 1);
+                at = self.doc_pos();
+            }
+            at = self.doc_mut().insert_str(at, &text);
+        }
+        self.jump_to(at.row as usize, at.col as usize);
+    }
+
+    /// The column of the first non-blank character on line `row`, or 0 if there is none.
+    fn first_non_blank(&self, row: usize) -> usize {
+        motion::first_non_blank(self.doc(), row)
+    }
+
+    /// Pull the cursor back onto the last character of its line, where Normal mode keeps it.
+    fn clamp_to_last_char(&mut self) {
+        let (row, col) = (self.cursor_row(), self.cursor_col());
+        self.jump_to(row, self.col_on_line(row, col, false));
+    }
+
+    /// `col` pulled back onto the start of a character on line `row`, its last one at the most, or
+    /// just past it with `past_end`.
+    fn col_on_line(&self, row: usize, col: usize, past_end: bool) -> usize {
+        let line = self.doc().get_line(row).unwrap_or_default();
+        let last = match past_end {
+            true => line.len(),
+            false => (line.char_indices().next_back()).map_or(0, |(col, _)| col),
+        };
+        let mut col = col.min(last);
+        while !line.is_char_boundary(col) {
+            col -= 1;
+        }
+        col
+    }
+
+    /// Switch to the buffer editing `path`, opening it in a new buffer unless it already is.
+    fn open_doc(&mut self, path: PathBuf) -> Result<(), CmdError> {
+        if let Some(ind) = (self.buffers.iter()).position(|buf| buf.doc.uri() == Some(&path)) {
+            self.switch_buffer(ind);
+            return Ok(());
+        }
+
+        let doc = match Document::open(&path) {
+            Ok(doc) => doc,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let mut doc = Document::default();
+                doc.set_uri(path);
+                doc
+            }
+            Err(err) => return Err(CmdError::Open(err)),
+        };
+
+        // an untouched, unnamed buffer (e.g. the one vix starts with) is simply reused
+        let current = self.doc();
+        if current.uri().is_none() && !current.dirty() && current.line_count() == 0 {
+            *self.doc_mut() = doc;
+            self.cursor = Position::default();
+            self.view_shift = Position::default();
+        } else {
+            self.buffers.push(DocBuffer::new(self.next_buf_id, doc));
+            self.next_buf_id += 1;
+            self.switch_buffer(self.buffers.len() - 1);
+        }
+        Ok(())
+    }
+
+    fn reload_doc(&mut self, path: PathBuf) -> Result<(), CmdError> {
+        *self.doc_mut() = Document::open(path).map_err(CmdError::Open)?;
+        (self.cursor, self.view_shift) = self.clamp_to_doc(self.cursor, self.view_shift);
+        Ok(())
+    }
+
+    /// Make the buffer at `ind` the current one, restoring where its cursor was left.
+    fn switch_buffer(&mut self, ind: usize) {
+        let current = &mut self.buffers[self.buf];
+        current.cursor = self.cursor;
+        current.view_shift = self.view_shift;
+
+        self.buf = ind;
+        self.cursor = self.buffers[ind].cursor;
+        self.view_shift = self.buffers[ind].view_shift;
+    }
+
+    /// Drop the buffer at `ind`, moving on to the next one or to a fresh buffer if it was the last.
+    fn close_buffer(&mut self, ind: usize) {
+        let closed = self.buffers[ind].id;
+        if self.buffers.len() == 1 {
+            self.buffers[0] = DocBuffer::new(self.next_buf_id, Document::default());
+            self.next_buf_id += 1;
+            self.cursor = Position::default();
+            self.view_shift = Position::default();
+        } else {
+            if ind == self.buf {
+                self.switch_buffer((ind + 1) % self.buffers.len());
+            }
+            self.buffers.remove(ind);
+            if self.buf > ind {
+                self.buf -= 1;
+            }
+        }
+
+        // other windows showing the closed buffer move on along with the focused one
+        let current = self.current_window();
+        for window in self
+            .windows
+            .iter_mut()
+            .filter(|window| window.buf == closed)
+        {
+            *window = Window {
+                buf: current.buf,
+                cursor: Position::default(),
+                view_shift: Position::default(),
+            };
+        }
+    }
+
+    /// The focused window as it currently stands.
+    fn current_window(&self) -> Window {
+        Window {
+            buf: self.buffers[self.buf].id,
+            cursor: self.cursor,
+            view_shift: self.view_shift,
+        }
+    }
+
+    /// Split the focused window in two along `dir`, moving focus to the new window above or to the
+    /// left.
+    fn split_window(&mut self, dir: Direction) {
+        self.windows[self.win] = self.current_window();
+        self.windows.push(self.current_window());
+        let new = self.windows.len() - 1;
+        self.splits.split(self.win, new, dir);
+        self.win = new;
+        self.jump_to(self.cursor_row(), self.cursor_col());
+    }
+
+    fn focus_window(&mut self, ind: usize) {
+        self.windows[self.win] = self.current_window();
+        self.win = ind;
+        self.load_window();
+    }
+
+    /// Take up the buffer, cursor and view of the focused window.
+    fn load_window(&mut self) {
+        let window = self.windows[self.win];
+        if let Some(ind) = (self.buffers.iter()).position(|buf| buf.id == window.buf) {
+            self.buf = ind;
+        }
+        self.cursor = window.cursor;
+        self.view_shift = window.view_shift;
+        // the buffer may have been edited from another window in the meantime
+        self.jump_to(self.cursor_row(), self.cursor_col());
+    }
+
+    /// Close the focused window, or quit if it is the only one.
+    fn quit_window(&mut self) {
+        if self.windows.len() == 1 {
+            self.running = false;
+            return;
+        }
+        // focus passes on to the next window, or the previous one if this was the last
+        let order = self.splits.order();
+        let pos = order.iter().position(|ind| *ind == self.win).unwrap_or(0);
+        let next = if pos + 1 < order.len() {
+            order[pos + 1]
+        } else {
+            order[pos - 1]
+        };
+
+        self.windows.remove(self.win);
+        self.splits.remove(self.win);
+        self.win = if next > self.win { next - 1 } else { next };
+        self.load_window();
+    }
+
+    #[inline]
+    fn doc(&self) -> &Document {
+        &self.buffers[self.buf].doc
+    }
+
+    #[inline]
+    fn doc_mut(&mut self) -> &mut Document {
+        &mut self.buffers[self.buf].doc
+    }
+
+    /// Save the current buffer, over a read-only file too if `force`d.
+    fn save_doc(&mut self, force: bool) -> CmdResult {
+        let result = if force {
+            self.doc_mut().force_save()
+        } else {
+            self.doc_mut().save()
+        };
+        match result {
+            Ok(()) => {}
+            Err(DocumentError::NoUri) => return Err(CmdError::NoFileName),
+            Err(DocumentError::ReadOnly) => return Err(CmdError::ReadOnly),
+            Err(err) => return Err(CmdError::Save(err)),
+        }
+        let name = self.doc().uri().unwrap_or(Path::new("")).display();
+        Ok(Some(format!(
+            "\"{}\" {} lines written",
+            name,
+            self.doc().line_count()
+        )))
+    }
+
+    /// Save every modified buffer, reporting how many were written and which could not be.
+    /// Refuse while any buffer, shown or hidden, has unsaved changes.
+    fn check_all_saved(&self) -> Result<(), CmdError> {
+        let dirty: Vec<_> = (self.buffers.iter())
+            .filter(|buf| buf.doc.dirty())
+            .map(DocBuffer::name)
+            .collect();
+        match dirty.is_empty() {
+            true => Ok(()),
+            false => Err(CmdError::UnsavedBuffers(dirty)),
+        }
+    }
+
+    /// Refuse to close the last window, and so quit, while any buffer has unsaved changes.
+    fn check_saved_before_quit(&self) -> Result<(), CmdError> {
+        match self.windows.len() {
+            1 => self.check_all_saved(),
+            _ => Ok(()),
+        }
+    }
+
+    fn save_all(&mut self) -> CmdResult {
+        let mut written = 0;
+        let mut failed = Vec::new();
+        for buffer in self.buffers.iter_mut().filter(|buf| buf.doc.dirty()) {
+            match buffer.doc.save() {
+                Ok(()) => written += 1,
+                Err(_) => failed.push(buffer.name()),
+            }
+        }
+        if !failed.is_empty() {
+            return Err(CmdError::SaveAll(written, failed));
+        }
+        Ok(Some(format!("{} written", written)))
+    }
+
+    //~ Rendering Logic
+
+    fn draw(&self, term: &mut Terminal<CrosstermBackend<Stdout>>) -> Result<(), AppError> {
+        term.draw(|frame| {
+            let area = frame.size();
+
+            let [_, status_area] = vertical![*=1, ==1].areas(area);
+            let (window_areas, separators) = self.layout();
+            for separator in separators {
+                let line = Block::default()
+                    .borders(Borders::LEFT)
+                    .border_style(Style::default().dark_gray());
+                frame.render_widget(line, separator);
+            }
+            for (ind, (window, area)) in self.windows.iter().zip(window_areas).enumerate() {
+                let focused = ind == self.win;
+                let window = if focused {
+                    self.current_window()
+                } else {
+                    *window
+                };
+                let Some(buffer) = self.buffers.iter().find(|buf| buf.id == window.buf) else {
+                    continue;
+                };
+                let view = DocView {
+                    app: self,
+                    doc: &buffer.doc,
+                    cursor: window.cursor,
+                    view_shift: window.view_shift,
+                    focused,
+                };
+                frame.render_widget(view, self.text_area(area));
+
+                if self.windows.len() > 1 && area.height > 0 {
+                    let bar_area = Rect {
+                        y: area.bottom() - 1,
+                        height: 1,
+                        ..area
+                    };
+                    let bar_style = if focused {
+                        Style::default().bold().reversed()
+                    } else {
+                        Style::default().dark_gray().reversed()
+                    };
+                    frame.render_widget(Line::styled(buffer.label(), bar_style), bar_area);
+                }
+            }
+
+            let mut status_line = match self.mode {
+                AppMode::Normal => {
+                    if let Some((msg, _)) = &self.message {
+                        msg.clone()
+                    } else {
+                        let mode = match self.insert_command {
+                            Some(_) => "(insert)",
+                            None => "NORMAL",
+                        };
+                        format!("{}  {}", mode, self.buffers[self.buf].label())
+                    }
+                }
+                AppMode::Command => match &self.completion {
+                    Some(completion) => {
+                        let candidates: Vec<_> = (completion.candidates.iter().enumerate())
+                            .map(|(ind, candidate)| {
+                                if ind == completion.index {
+                                    format!("[{}]", candidate)
+                                } else {
+                                    candidate.clone()
+                                }
+                            })
+                            .collect();
+                        let candidates = candidates.join("  ");
+                        format!("{}{}    {}", CMD_PROMPT, self.cmd, candidates)
+                    }
+                    None => format!("{}{}", CMD_PROMPT, self.cmd),
+                },
+                AppMode::Search { .. } => {
+                    format!("{}{}", self.prompt().unwrap_or_default(), self.cmd)
+                }
+                AppMode::Insert => "INSERT".to_string(),
+                AppMode::Replace => "REPLACE".to_string(),
+                AppMode::Visual => "VISUAL".to_string(),
+                AppMode::VisualLine => "VISUAL LINE".to_string(),
+                AppMode::VisualBlock => "VISUAL BLOCK".to_string(),
+                AppMode::Confirm => match &self.confirm {
+                    Some(confirm) => {
+                        format!("Replace with `{}` (y/n/a/q)?", confirm.sub.replacement)
+                    }
+                    None => String::default(),
+                },
+            };
+            if let Some((name, _)) = self.recording.as_ref().filter(|_| self.prompt().is_none()) {
+                status_line = format!("{}  recording @{}", status_line, name);
+            }
+            let status_style = match self.mode {
+                AppMode::Normal => match &self.message {
+                    Some((_, Severity::Error)) => Style::default().bold().on_red(),
+                    Some((_, Severity::Info)) | None => Style::default().bold().on_light_blue(),
+                },
+                AppMode::Command | AppMode::Search { .. } => {
+                    Style::default().bold().black().on_light_yellow()
                 }
-                AppMode::Command => Style::default().bold().black().on_light_yellow(),
                 AppMode::Insert => Style::default().bold().black().on_green(),
+                AppMode::Replace => Style::default().bold().black().on_light_magenta(),
+                AppMode::Visual | AppMode::VisualLine | AppMode::VisualBlock => {
+                    Style::default().bold().black().on_light_cyan()
+                }
+                AppMode::Confirm => Style::default().bold().black().on_light_yellow(),
             };
             frame.render_widget(Line::styled(status_line, status_style), status_area);
 
-            if self.show_help {
-                let popup_layout = centered_rect(frame.size(), 35, 53);
-                frame.render_widget(Clear, popup_layout);
-                frame.render_widget(self.help_widget(), popup_layout);
+            match &self.popup {
+                Some(Popup::Help { scroll }) => {
+                    let scroll = *scroll;
+                    let popup_layout = help_rect(frame.size());
+                    frame.render_widget(Clear, popup_layout);
+                    frame.render_widget(help_widget(scroll), popup_layout);
+
+                    let mut state = ScrollbarState::new(help_lines().len())
+                        .viewport_content_length(popup_layout.height.saturating_sub(2) as usize)
+                        .position(scroll as usize);
+                    let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight);
+                    frame.render_stateful_widget(scrollbar, popup_layout, &mut state);
+                }
+                Some(Popup::Buffers { number }) => {
+                    let popup_layout = centered_rect(frame.size(), 50, 50);
+                    frame.render_widget(Clear, popup_layout);
+                    frame.render_widget(self.buffers_widget(*number), popup_layout);
+                }
+                Some(Popup::Marks) => {
+                    let popup_layout = centered_rect(frame.size(), 60, 50);
+                    frame.render_widget(Clear, popup_layout);
+                    frame.render_widget(self.marks_widget(), popup_layout);
+                }
+                Some(Popup::Registers(names)) => {
+                    let popup_layout = centered_rect(frame.size(), 60, 50);
+                    frame.render_widget(Clear, popup_layout);
+                    let width = popup_layout.width.saturating_sub(12) as usize;
+                    frame.render_widget(self.registers_widget(names, width), popup_layout);
+                }
+                None => {}
             }
         })?;
 
         Ok(())
     }
 
-    fn help_widget(&self) -> impl Widget {
-        let text = vec![
-            line!["ViX - A Vi-like Text Editor"],
-            line![],
-            line![],
-            line!["`:q!`     - to quit vix forcefully      "],
-            line!["`:h`      - to display this help message"],
-            line!["`:w`      - to save a file              "],
-            line!["`:w file` - to save a file to `file`    "],
-        ];
+    fn buffers_widget(&self, number: Option<usize>) -> impl Widget {
+        let text: Vec<_> = (self.buffers.iter().enumerate())
+            .map(|(ind, buffer)| {
+                let current = if ind == self.buf { '%' } else { ' ' };
+                let dirty = if buffer.doc.dirty() { '+' } else { ' ' };
+                Line::from(format!(
+                    "{:>3} {}{} \"{}\"",
+                    buffer.id,
+                    current,
+                    dirty,
+                    buffer.name()
+                ))
+            })
+            .collect();
+
+        let mut block = Block::default()
+            .borders(Borders::ALL)
+            .title("Buffers (number and Enter to switch, Esc to close)");
+        if let Some(number) = number {
+            block = block.title_bottom(format!(" {} ", number));
+        }
+        Paragraph::new(text).block(block)
+    }
+
+    fn marks_widget(&self) -> impl Widget {
+        let doc = self.doc();
+        let mut text = vec![line!["mark  line   col  text"].bold()];
+        for (mark, pos) in doc.marks() {
+            let preview = doc
+                .get_line(pos.row as usize)
+                .unwrap_or_default()
+                .trim_start();
+            text.push(Line::from(format!(
+                " {}   {:>5} {:>5}  {}",
+                mark,
+                pos.row + 1,
+                pos.col,
+                preview
+            )));
+        }
+        if text.len() == 1 {
+            text.push(line!["No marks set"]);
+        }
+
+        Paragraph::new(text).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Marks (Esc to close)"),
+        )
+    }
+
+    /// List `names` (every register if empty), previews cut to `width` characters.
+    fn registers_widget(&self, names: &[char], width: usize) -> impl Widget {
+        let mut text = vec![line!["type name content"].bold()];
+        let registers = self.registers.list();
+        let shown = (registers.iter()).filter(|(name, _)| names.is_empty() || names.contains(name));
+        for (name, register) in shown {
+            let kind = match register {
+                Register::Linewise(_) => 'l',
+                Register::Charwise(_) => 'c',
+                Register::Blockwise(_) => 'b',
+            };
+            let preview = register.preview(width);
+            text.push(Line::from(format!("  {}  \"{}   {}", kind, name, preview)));
+        }
+        if text.len() == 1 {
+            text.push(line!["Nothing in these registers"]);
+        }
 
-        Paragraph::new(text)
-            .block(Block::default().borders(Borders::ALL))
-            .alignment(Alignment::Center)
+        Paragraph::new(text).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Registers (Esc to close)"),
+        )
     }
 
     //~ Handling Event
 
-    fn handle_event(
-        &self,
-        event: Event,
-        term: &Terminal<CrosstermBackend<Stdout>>,
-    ) -> Result<AppAction, AppError> {
+    fn handle_event(&self, event: Event) -> Result<AppAction, AppError> {
         match event {
-            Event::Resize(_, _) => self.handle_event_cursor(term, Move::None),
+            Event::Resize(_, _) => self.handle_event_cursor(Move::None),
+            event if self.popup.is_some() => self.handle_event_popup(event),
             event => match self.mode {
-                AppMode::Normal => self.handle_event_normal(event, term),
+                AppMode::Normal => self.handle_event_normal(event),
                 AppMode::Insert => self.handle_event_insert(event),
-                AppMode::Command => self.handle_event_command(event),
+                AppMode::Replace => self.handle_event_replace(event),
+                AppMode::Visual | AppMode::VisualLine | AppMode::VisualBlock => {
+                    self.handle_event_visual(event)
+                }
+                AppMode::Command | AppMode::Search { .. } => self.handle_event_command(event),
+                AppMode::Confirm => self.handle_event_confirm(event),
             },
         }
     }
 
-    fn handle_event_normal(
-        &self,
-        event: Event,
-        term: &Terminal<CrosstermBackend<Stdout>>,
-    ) -> Result<AppAction, AppError> {
+    fn handle_event_normal(&self, event: Event) -> Result<AppAction, AppError> {
         match event {
-            Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
-                KeyCode::Char('h') | KeyCode::Left => self.handle_event_cursor(term, Move::Left),
-                KeyCode::Char('j') | KeyCode::Down => self.handle_event_cursor(term, Move::Down),
-                KeyCode::Char('k') | KeyCode::Up => self.handle_event_cursor(term, Move::Up),
-                KeyCode::Char('l') | KeyCode::Right => self.handle_event_cursor(term, Move::Right),
-                KeyCode::Char('i') => Ok(AppAction::EnterMode(AppMode::Insert)),
-                KeyCode::Char(':') => Ok(AppAction::EnterMode(AppMode::Command)),
-                _ => Ok(AppAction::None),
+            Event::Key(key) if key.kind == KeyEventKind::Press => match resolve(self.keys, key) {
+                (_, Some(action)) => Ok(action),
+                (_, None) => self.handle_event_in_context(key),
             },
+            Event::Paste(text) => Ok(AppAction::PasteRegister(text)),
             _ => Ok(AppAction::None),
         }
     }
 
-    fn handle_event_cursor(
-        &self,
-        term: &Terminal<CrosstermBackend<Stdout>>,
-        mv: Move,
-    ) -> Result<AppAction, AppError> {
-        let width = term.size()?.width.saturating_sub(1);
-        let height = term.size()?.height.saturating_sub(2);
-        let doc_height = self.doc.line_count().saturating_sub(1);
+    /// Act on a key [`resolve`] leaves to the cursor, the view or the windows to decide.
+    fn handle_event_in_context(&self, key: KeyEvent) -> Result<AppAction, AppError> {
+        let count = self.keys.count;
+        match self.keys.pending {
+            Some(Pending::Window) => Ok(self.window_key(key.code)),
+            Some(Pending::Operator(op, before)) => {
+                let count = self.keys.combined_count(before);
+                Ok(self.motion_key(Some(op), key.code, count))
+            }
+            _ => match key.code {
+                KeyCode::Char('h') | KeyCode::Left => self.handle_event_cursor(Move::Left),
+                // moving off the first or last line fails, which stops a macro
+                KeyCode::Char('j') | KeyCode::Down if self.cursor_row() == self.last_row() => {
+                    Ok(AppAction::Fail)
+                }
+                KeyCode::Char('k') | KeyCode::Up if self.cursor_row() == 0 => Ok(AppAction::Fail),
+                KeyCode::Char('j') | KeyCode::Down => self.handle_event_cursor(Move::Down),
+                KeyCode::Char('k') | KeyCode::Up => self.handle_event_cursor(Move::Up),
+                KeyCode::Char('l') | KeyCode::Right => self.handle_event_cursor(Move::Right),
+                KeyCode::Char('q') if self.recording.is_some() => Ok(AppAction::StopRecording),
+                KeyCode::Char('q') => Ok(AppAction::Pending(Pending::Record)),
+                code => Ok(self.motion_key(None, code, count)),
+            },
+        }
+    }
+
+    /// Focus the window `code` stands for after `Ctrl-w`.
+    fn window_key(&self, code: KeyCode) -> AppAction {
+        let towards = match code {
+            KeyCode::Char('h') | KeyCode::Left => Move::Left,
+            KeyCode::Char('j') | KeyCode::Down => Move::Down,
+            KeyCode::Char('k') | KeyCode::Up => Move::Up,
+            KeyCode::Char('l') | KeyCode::Right => Move::Right,
+            KeyCode::Char('w') => {
+                let order = self.splits.order();
+                let pos = order.iter().position(|ind| *ind == self.win).unwrap_or(0);
+                return AppAction::FocusWindow(order[(pos + 1) % order.len()]);
+            }
+            _ => return AppAction::None,
+        };
+        match self.window_towards(towards) {
+            Some(ind) => AppAction::FocusWindow(ind),
+            None => AppAction::None,
+        }
+    }
+
+    /// Move by the motion `code` stands for, or apply `op` over it.
+    fn motion_key(&self, op: Option<Operator>, code: KeyCode, count: Option<usize>) -> AppAction {
+        match code {
+            KeyCode::Char(';') => return self.repeat_find(op, false, count),
+            KeyCode::Char(',') => return self.repeat_find(op, true, count),
+            _ => {}
+        }
+        let at = match code {
+            KeyCode::Char('H') => Some(ScreenRow::Top),
+            KeyCode::Char('M') => Some(ScreenRow::Middle),
+            KeyCode::Char('L') => Some(ScreenRow::Bottom),
+            _ => None,
+        };
+        if let Some(at) = at {
+            let line = self.screen_line(at, count) + 1;
+            return motion_action(op, Motion::ToLine(Some(line)), None);
+        }
+        match key_motion(code, count) {
+            Some(motion) => motion_action(op, motion, count),
+            None => AppAction::None,
+        }
+    }
+
+    /// The line shown at `at` in the window, or `count` lines from its top or bottom. That stays
+    /// `scrolloff` lines in from an edge the view can still scroll past.
+    fn screen_line(&self, at: ScreenRow, count: Option<usize>) -> usize {
+        let (top, height) = (self.view_shift.row as usize, self.area().height as usize);
+        let bottom = (top + height)
+            .min(self.doc().line_count())
+            .saturating_sub(1);
+        let scrolloff = (self.options.scrolloff as usize).min(height.saturating_sub(1) / 2);
+        let offset = count.unwrap_or(1).saturating_sub(1);
+        match at {
+            ScreenRow::Top => {
+                let scrolloff = if top > 0 { scrolloff } else { 0 };
+                (top + offset.max(scrolloff)).min(bottom)
+            }
+            ScreenRow::Middle => top + (bottom - top) / 2,
+            ScreenRow::Bottom => {
+                let scrolloff = if bottom < self.last_row() {
+                    scrolloff
+                } else {
+                    0
+                };
+                bottom.saturating_sub(offset.max(scrolloff)).max(top)
+            }
+        }
+    }
+
+    /// Repeat the last `f`, `F`, `t` or `T`, the other way round with `reverse`.
+    fn repeat_find(&self, op: Option<Operator>, reverse: bool, count: Option<usize>) -> AppAction {
+        let Some(mut find) = self.last_find else {
+            return AppAction::None;
+        };
+        find.backward ^= reverse;
+        let mut count = count.unwrap_or(1);
+        // `;` after `tx` goes on to the next `x` instead of staying right before this one
+        if find.till && find.is_adjacent(self.doc(), self.doc_pos()) {
+            count += 1;
+        }
+        motion_action(op, Motion::Find(find), Some(count))
+    }
+
+    fn handle_event_cursor(&self, mv: Move) -> Result<AppAction, AppError> {
+        let area = self.area();
+        let width = (area.width)
+            .saturating_sub(self.gutter_width())
+            .saturating_sub(1);
+        let height = area.height.saturating_sub(1);
+        let doc_height = self.doc().line_count().saturating_sub(1);
 
         let mut view_shift = self.view_shift;
         let mut cursor = match mv {
@@ -421,8 +3721,21 @@ impl App {
         warn!("cursor: {:?}", cursor);
         warn!("view_shift: {:?}", view_shift);
 
+        // keep `scrolloff` lines visible above and below the cursor
+        let scrolloff = cmp::min(self.options.scrolloff, height / 2);
+        while cursor.row < scrolloff && view_shift.row > 0 {
+            view_shift.row = view_shift.row.saturating_sub(1);
+            cursor.row = cursor.row.saturating_add(1);
+        }
+        while cursor.row > height.saturating_sub(scrolloff)
+            && (view_shift.row as usize + height as usize) < doc_height
+        {
+            view_shift.row = view_shift.row.saturating_add(1);
+            cursor.row = cursor.row.saturating_sub(1);
+        }
+
         let ln_len = self
-            .doc
+            .doc()
             .get_line_len(view_shift.row as usize + cursor.row as usize);
         let last_col = cmp::min(
             ln_len.saturating_sub(view_shift.col as usize),
@@ -448,7 +3761,30 @@ impl App {
             cursor.row = cursor.row.saturating_sub(1);
         }
 
+        let (cursor, view_shift) = self.clamp_to_doc(cursor, view_shift);
+
+        warn!("cursor: {:?}", cursor);
+        warn!("view_shift: {:?}", view_shift);
+
+        Ok(AppAction::CursorViewChange { cursor, view_shift })
+    }
+
+    /// Pull `cursor` and `view_shift` back so that they point inside the document.
+    fn clamp_to_doc(&self, mut cursor: Position, mut view_shift: Position) -> (Position, Position) {
+        let doc_height = self.doc().line_count().saturating_sub(1);
+
+        // vertical
+        while (cursor.row as usize).saturating_add(view_shift.row as usize) > doc_height {
+            if cursor.row != 0 {
+                cursor.row = cursor.row.saturating_sub(1);
+            } else {
+                view_shift.row = view_shift.row.saturating_sub(1);
+            }
+        }
+
         // horizontal
+        let row = view_shift.row as usize + cursor.row as usize;
+        let ln_len = self.doc().get_line_len(row);
         while (cursor.col as usize).saturating_add(view_shift.col as usize) > ln_len {
             if cursor.col != 0 {
                 cursor.col = cursor.col.saturating_sub(1);
@@ -457,25 +3793,44 @@ impl App {
             }
         }
 
-        // vertical
-        while (cursor.row as usize).saturating_add(view_shift.row as usize) > doc_height {
-            if cursor.row != 0 {
-                cursor.row = cursor.row.saturating_sub(1);
-            } else {
-                view_shift.row = view_shift.row.saturating_sub(1);
-            }
+        // and within the window, where a tab may take up several columns
+        let width = self.area().width.saturating_sub(self.gutter_width()) as usize;
+        let line = self.doc().get_line(row).unwrap_or_default();
+        while cursor.col != 0 && self.screen_col(cursor, view_shift) >= width {
+            let rest = line.get(view_shift.col as usize..);
+            let step = rest
+                .and_then(|rest| rest.chars().next())
+                .map_or(1, char::len_utf8) as u16;
+            let step = step.min(cursor.col);
+            view_shift.col += step;
+            cursor.col -= step;
         }
 
-        warn!("cursor: {:?}", cursor);
-        warn!("view_shift: {:?}", view_shift);
+        (cursor, view_shift)
+    }
 
-        Ok(AppAction::CursorViewChange { cursor, view_shift })
+    /// How far into the text area of the window `cursor` shows, the tabs on its line taking up
+    /// as many columns as they are drawn with.
+    fn screen_col(&self, cursor: Position, view_shift: Position) -> usize {
+        let (doc, tabstop) = (self.doc(), self.options.tabstop as usize);
+        let row = view_shift.row as usize + cursor.row as usize;
+        let col = view_shift.col as usize + cursor.col as usize;
+        doc.display_col(row, col, tabstop) - doc.display_col(row, view_shift.col as usize, tabstop)
     }
 
     fn handle_event_insert(&self, event: Event) -> Result<AppAction, AppError> {
         match event {
             Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
                 KeyCode::Esc => Ok(AppAction::EnterMode(AppMode::Normal)),
+                KeyCode::Char('w') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    Ok(AppAction::DeleteWordBefore)
+                }
+                KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    Ok(AppAction::DeleteLineBefore)
+                }
+                KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    Ok(AppAction::InsertCommand)
+                }
                 KeyCode::Char(ch) => Ok(AppAction::InsertChar(ch)),
                 KeyCode::Backspace => {
                     if self.cursor.col != 0 {
@@ -486,20 +3841,246 @@ impl App {
                         Ok(AppAction::None)
                     }
                 }
+                KeyCode::Delete => Ok(AppAction::DeleteForward),
                 KeyCode::Enter => Ok(AppAction::NewLine),
+                KeyCode::Left => self.handle_event_cursor(Move::Left),
+                KeyCode::Down => self.handle_event_cursor(Move::Down),
+                KeyCode::Up => self.handle_event_cursor(Move::Up),
+                KeyCode::Right => self.handle_event_cursor(Move::Right),
+                KeyCode::Home => Ok(AppAction::Motion(Motion::LineStart, 1)),
+                KeyCode::End => Ok(AppAction::Motion(Motion::LineEnd, 1)),
+                KeyCode::PageDown | KeyCode::PageUp => Ok(AppAction::Page {
+                    down: key.code == KeyCode::PageDown,
+                    count: 1,
+                }),
+                KeyCode::Tab => Ok(AppAction::InsertTab),
+                KeyCode::BackTab => Ok(AppAction::DedentLine),
+                _ => Ok(AppAction::None),
+            },
+            Event::Paste(text) => Ok(AppAction::InsertText(text)),
+            _ => Ok(AppAction::None),
+        }
+    }
+
+    fn handle_event_replace(&self, event: Event) -> Result<AppAction, AppError> {
+        match event {
+            Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Esc => Ok(AppAction::EnterMode(AppMode::Normal)),
+                KeyCode::Char(ch) => Ok(AppAction::ReplaceChar(ch)),
+                KeyCode::Backspace => Ok(AppAction::ReplaceBackspace),
+                KeyCode::Enter => Ok(AppAction::ReplaceNewLine),
                 _ => Ok(AppAction::None),
             },
             _ => Ok(AppAction::None),
         }
     }
 
+    /// Keys in Visual mode, where moving the cursor grows or shrinks the selection.
+    fn handle_event_visual(&self, event: Event) -> Result<AppAction, AppError> {
+        if let Event::Key(key) = event {
+            if key.kind == KeyEventKind::Press && self.keys.pending.is_none() {
+                let block = self.mode == AppMode::VisualBlock;
+                match key.code {
+                    KeyCode::Esc => return Ok(AppAction::EnterMode(AppMode::Normal)),
+                    KeyCode::Char('v') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        return Ok(self.switch_visual(AppMode::VisualBlock))
+                    }
+                    KeyCode::Char('v') => return Ok(self.switch_visual(AppMode::Visual)),
+                    KeyCode::Char('V') => return Ok(self.switch_visual(AppMode::VisualLine)),
+                    KeyCode::Char('I') if block => {
+                        return Ok(AppAction::BlockInsert { append: false })
+                    }
+                    KeyCode::Char('A') if block => {
+                        return Ok(AppAction::BlockInsert { append: true })
+                    }
+                    KeyCode::Char(kind @ ('i' | 'a')) => {
+                        let pending = Pending::Object(None, self.keys.count, kind == 'a');
+                        return Ok(AppAction::Pending(pending));
+                    }
+                    _ => {}
+                }
+                let op = match key.code {
+                    KeyCode::Char('x') => Some(Operator::Delete),
+                    KeyCode::Char('u') => Some(Operator::Case(Case::Lower)),
+                    KeyCode::Char('U') => Some(Operator::Case(Case::Upper)),
+                    KeyCode::Char('~') => Some(Operator::Case(Case::Toggle)),
+                    code => key_operator(code),
+                };
+                if let Some(op) = op {
+                    return Ok(AppAction::OperateSelection(
+                        op,
+                        self.keys.count.unwrap_or(1),
+                    ));
+                }
+            }
+        }
+        // otherwise only the movements of Normal mode apply
+        match self.handle_event_normal(event)? {
+            action @ (AppAction::CursorViewChange { .. }
+            | AppAction::Fail
+            | AppAction::Motion(..)
+            | AppAction::Count(_)
+            | AppAction::HalfPage { .. }
+            | AppAction::Page { .. }
+            | AppAction::PlaceView(_)
+            | AppAction::SearchNext { .. }
+            | AppAction::Object(None, ..)
+            | AppAction::SetMark(_)
+            | AppAction::ToMark { op: None, .. }
+            | AppAction::Pending(
+                Pending::G(None, _)
+                | Pending::Find(None, ..)
+                | Pending::Object(None, ..)
+                | Pending::Z
+                | Pending::Mark
+                | Pending::ToMark(None, _),
+            )) => Ok(action),
+            _ => Ok(AppAction::None),
+        }
+    }
+
+    /// Select `range`, in Visual Line or Visual Block mode if it is linewise or blockwise.
+    fn select(&mut self, range: TextRange) {
+        let (start, end) = (range.start, range.end);
+        if range.kind == RangeKind::Linewise {
+            self.mode = AppMode::VisualLine;
+            self.anchor = start;
+            let row = end.row as usize;
+            return self.jump_to(row, self.first_non_blank(row));
+        }
+        let line = self.doc().get_line(end.row as usize).unwrap_or_default();
+        let last = (line.get(..end.col as usize))
+            .and_then(|before| before.char_indices().next_back())
+            .map_or(0, |(col, _)| col);
+        self.mode = match range.kind {
+            RangeKind::Blockwise => AppMode::VisualBlock,
+            _ => AppMode::Visual,
+        };
+        self.anchor = start;
+        self.jump_to(end.row as usize, last);
+    }
+
+    /// Switch to Visual mode `mode`, or back to Normal mode if already in it.
+    fn switch_visual(&self, mode: AppMode) -> AppAction {
+        match self.mode == mode {
+            true => AppAction::EnterMode(AppMode::Normal),
+            false => AppAction::EnterMode(mode),
+        }
+    }
+
     fn handle_event_command(&self, event: Event) -> Result<AppAction, AppError> {
         match event {
+            Event::Key(key) if key.kind == KeyEventKind::Press && self.keys.pending.is_some() => {
+                match key.code {
+                    KeyCode::Char(name) => Ok(self.insert_register(name)),
+                    _ => Ok(AppAction::None),
+                }
+            }
             Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
                 KeyCode::Esc => Ok(AppAction::EnterMode(AppMode::Normal)),
+                KeyCode::Char('w') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    Ok(AppAction::CmdDeleteWord)
+                }
+                KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    Ok(AppAction::Pending(Pending::Register))
+                }
                 KeyCode::Char(ch) => Ok(AppAction::CmdPush(ch)),
                 KeyCode::Backspace => Ok(AppAction::CmdPop),
+                KeyCode::Delete => Ok(AppAction::CmdDelete),
+                KeyCode::Left => {
+                    let before = self.cmd[..self.cmd_cursor].chars().next_back();
+                    let len = before.map_or(0, char::len_utf8);
+                    Ok(AppAction::CmdCursor(self.cmd_cursor - len))
+                }
+                KeyCode::Right => {
+                    let after = self.cmd[self.cmd_cursor..].chars().next();
+                    let len = after.map_or(0, char::len_utf8);
+                    Ok(AppAction::CmdCursor(self.cmd_cursor + len))
+                }
+                KeyCode::Home => Ok(AppAction::CmdCursor(0)),
+                KeyCode::End => Ok(AppAction::CmdCursor(self.cmd.len())),
                 KeyCode::Enter => Ok(AppAction::CmdEnter),
+                // completion and history are those of commands
+                _ if self.mode != AppMode::Command => Ok(AppAction::None),
+                KeyCode::Tab => Ok(AppAction::CmdComplete),
+                KeyCode::Up => Ok(AppAction::CmdHistoryPrev),
+                KeyCode::Down => Ok(AppAction::CmdHistoryNext),
+                _ => Ok(AppAction::None),
+            },
+            // the lines of a paste are run together, as a command is a single line
+            Event::Paste(text) => {
+                let text = line_breaks(&text).trim_end_matches('\n').replace('\n', " ");
+                Ok(AppAction::CmdInsert(text))
+            }
+            _ => Ok(AppAction::None),
+        }
+    }
+
+    /// Put register `name` into the command line, `/` being the last searched pattern.
+    fn insert_register(&self, name: char) -> AppAction {
+        let text = match name {
+            '/' => self.search.pattern.clone(),
+            name => self.registers.get(name).map(Register::joined),
+        };
+        match text {
+            Some(text) => AppAction::CmdInsert(text),
+            None => AppAction::None,
+        }
+    }
+
+    fn handle_event_popup(&self, event: Event) -> Result<AppAction, AppError> {
+        match event {
+            Event::Key(key) if key.kind == KeyEventKind::Press => match &self.popup {
+                Some(Popup::Help { scroll }) => Ok(self.handle_event_help(key, *scroll)),
+                Some(Popup::Buffers { number }) => Ok(self.handle_event_buffers(key, *number)),
+                Some(Popup::Marks | Popup::Registers(_)) => Ok(AppAction::ClosePopup),
+                None => Ok(AppAction::None),
+            },
+            _ => Ok(AppAction::None),
+        }
+    }
+
+    /// Scroll the help text, which stays open until it is explicitly closed.
+    fn handle_event_help(&self, key: KeyEvent, scroll: u16) -> AppAction {
+        let height = help_rect(self.size).height.saturating_sub(2);
+        let last = (help_lines().len() as u16).saturating_sub(height);
+        let half_page = cmp::max(height / 2, 1);
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let scroll = match key.code {
+            KeyCode::Char('d') if ctrl => scroll.saturating_add(half_page),
+            KeyCode::Char('u') if ctrl => scroll.saturating_sub(half_page),
+            KeyCode::Char('j') | KeyCode::Down => scroll.saturating_add(1),
+            KeyCode::Char('k') | KeyCode::Up => scroll.saturating_sub(1),
+            KeyCode::Char('q') | KeyCode::Esc => return AppAction::ClosePopup,
+            _ => return AppAction::None,
+        };
+        AppAction::ScrollHelp(cmp::min(scroll, last))
+    }
+
+    /// Take in the number of the buffer to switch to, which may run to several digits, until
+    /// Enter confirms it.
+    fn handle_event_buffers(&self, key: KeyEvent, number: Option<usize>) -> AppAction {
+        match (key.code, number) {
+            (KeyCode::Char(ch @ '0'..='9'), _) => {
+                let digit = ch as usize - '0' as usize;
+                let number = number.unwrap_or(0).saturating_mul(10).saturating_add(digit);
+                AppAction::BufferNumber(Some(number))
+            }
+            (KeyCode::Backspace, Some(number)) => {
+                AppAction::BufferNumber(Some(number / 10).filter(|_| number >= 10))
+            }
+            (KeyCode::Enter, Some(number)) => AppAction::SwitchBuffer(number),
+            _ => AppAction::ClosePopup,
+        }
+    }
+
+    fn handle_event_confirm(&self, event: Event) -> Result<AppAction, AppError> {
+        match event {
+            Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('y') => Ok(AppAction::Confirm(ConfirmAnswer::Yes)),
+                KeyCode::Char('n') => Ok(AppAction::Confirm(ConfirmAnswer::No)),
+                KeyCode::Char('a') => Ok(AppAction::Confirm(ConfirmAnswer::All)),
+                KeyCode::Char('q') | KeyCode::Esc => Ok(AppAction::Confirm(ConfirmAnswer::Quit)),
                 _ => Ok(AppAction::None),
             },
             _ => Ok(AppAction::None),
@@ -513,29 +4094,199 @@ impl Default for App {
             mode: AppMode::default(),
             cursor: Position::default(),
             view_shift: Position::default(),
-            show_help: true,
+            options: Options::default(),
             running: true,
-            doc: Document::default(),
+            size: Rect::default(),
+            buffers: vec![DocBuffer::new(1, Document::default())],
+            buf: 0,
+            next_buf_id: 2,
+            windows: vec![Window {
+                buf: 1,
+                cursor: Position::default(),
+                view_shift: Position::default(),
+            }],
+            win: 0,
+            splits: Split::default(),
+            keys: PendingInput::default(),
             cmd: String::default(),
-            msg: String::default(),
+            cmd_cursor: 0,
+            message: None,
+            confirm: None,
+            cmd_history: History::default(),
+            completion: None,
+            search: Search::default(),
+            shell_cmd: None,
+            popup: Some(Popup::Help { scroll: 0 }),
+            aliases: BTreeMap::new(),
+            prev_dir: None,
+            syntax_on: true,
+            registers: Registers::default(),
+            clipboard: Clipboard::default(),
+            last_find: None,
+            replaced: Vec::new(),
+            typed: Vec::new(),
+            last_change: Vec::new(),
+            recording: None,
+            last_macro: None,
+            queue: VecDeque::new(),
+            anchor: Position::default(),
+            last_visual: None,
+            block_insert: None,
+            repeated_insert: None,
+            insert_start: Position::default(),
+            insert_command: None,
+            want_col: None,
         }
     }
 }
 
-impl Widget for &App {
+/// A window's view onto its document.
+struct DocView<'a> {
+    app: &'a App,
+    doc: &'a Document,
+    cursor: Position,
+    view_shift: Position,
+    focused: bool,
+}
+
+impl Widget for DocView<'_> {
     fn render(self, area: Rect, buf: &mut Buffer)
     where
         Self: Sized,
     {
+        let gutter = self.app.doc_gutter_width(self.doc);
+        // lines are cut off at the right edge of the window
+        let text_width = area.width.saturating_sub(gutter) as usize;
+        let tabstop = self.app.options.tabstop as usize;
+        // the lines are scrolled by as many screen columns as the shift takes up on the cursor's
+        let cursor_row = (self.view_shift.row + self.cursor.row) as usize;
+        let shift = self
+            .doc
+            .display_col(cursor_row, self.view_shift.col as usize, tabstop);
+        // the screen column of byte `col` of line `row`, those past its end taking one each
+        let x_of = |row: usize, col: usize| {
+            let past_end = col.saturating_sub(self.doc.get_line_len(row));
+            self.doc.display_col(row, col, tabstop) + past_end
+        };
+        // where screen columns `from..to` of the line on screen row `row` show, if in view
+        let span = |row: u16, from: usize, to: usize| {
+            let from = from.max(shift);
+            let x = area.x + gutter + (from - shift) as u16;
+            (to > from)
+                .then(|| Rect::new(x, area.y + row, (to - from) as u16, 1).intersection(area))
+        };
         for row in 0..area.height {
-            if let Some(ln) = self.doc.get_line((self.view_shift.row + row) as usize) {
-                if let Some(ln) = ln.get(self.view_shift.col as usize..) {
-                    buf.set_string(0, row, ln, Style::default());
+            let ind = (self.view_shift.row + row) as usize;
+            if let Some(ln) = self.doc.display_line(ind, tabstop) {
+                if gutter != 0 {
+                    let number = format!("{:>1$} ", ind + 1, gutter as usize - 1);
+                    let style = Style::default().dark_gray();
+                    buf.set_stringn(area.x, area.y + row, number, area.width as usize, style);
+                }
+                let mut cols = ln.char_indices().map(|(col, _)| col).chain([ln.len()]);
+                if let Some(start) = cols.nth(shift) {
+                    let x = area.x + gutter;
+                    buf.set_stringn(x, area.y + row, &ln[start..], text_width, Style::default());
                 } else {
-                    buf.set_string(0, row, "<", Style::default().dark_gray())
+                    let style = Style::default().dark_gray();
+                    buf.set_stringn(area.x + gutter, area.y + row, "<", text_width, style);
                 }
             } else {
-                buf.set_string(0, row, "~", Style::default().dark_gray())
+                buf.set_string(area.x, area.y + row, "~", Style::default().dark_gray())
+            }
+        }
+
+        // syntax, worked out afresh for the lines in view
+        if let Some(syntax) = syntax::detect(self.doc.uri()).filter(|_| self.app.syntax_on) {
+            for row in 0..area.height {
+                let ind = (self.view_shift.row + row) as usize;
+                let Some(ln) = self.doc.get_line(ind) else {
+                    break;
+                };
+                for (start, end, token) in syntax.highlight(ln) {
+                    let Some(highlight) = span(row, x_of(ind, start), x_of(ind, end)) else {
+                        continue;
+                    };
+                    let style = match token {
+                        Token::Keyword => Style::default().light_magenta(),
+                        Token::Number => Style::default().light_cyan(),
+                        Token::String => Style::default().light_green(),
+                        Token::Comment => Style::default().dark_gray(),
+                    };
+                    buf.set_style(highlight, style);
+                }
+            }
+        }
+
+        // matches of the last search
+        if let Some(pattern) = self.app.search.pattern.as_deref() {
+            if self.app.search.highlight && self.app.options.hlsearch && !pattern.is_empty() {
+                for row in 0..area.height {
+                    let ind = (self.view_shift.row + row) as usize;
+                    let Some(ln) = self.doc.get_line(ind) else {
+                        break;
+                    };
+                    for (col, _) in ln.match_indices(pattern) {
+                        let (from, to) = (x_of(ind, col), x_of(ind, col + pattern.len()));
+                        if let Some(highlight) = span(row, from, to) {
+                            buf.set_style(highlight, Style::default().black().on_yellow());
+                        }
+                    }
+                }
+            }
+        }
+
+        // the Visual mode selection, taking in the line break after all but its last line
+        if self.focused && self.app.mode.is_visual() {
+            let (start, end) = match self.app.mode {
+                AppMode::VisualBlock => self.app.block(),
+                _ => self.app.selection(),
+            };
+            let first = start.row.max(self.view_shift.row);
+            let last = end
+                .row
+                .min(self.view_shift.row + area.height.saturating_sub(1));
+            for ind in first..=last {
+                let (row, ind) = (ind - self.view_shift.row, ind as usize);
+                let ln = self.doc.get_line(ind).unwrap_or_default();
+                // in screen columns, mapped from the bytes they start and end at
+                let (from, to) = match self.app.mode {
+                    // whole lines, however long
+                    AppMode::VisualLine => (0, shift + text_width),
+                    // the columns of the block, on the lines reaching into it
+                    AppMode::VisualBlock => {
+                        let to = (end.col as usize).min(ln.len());
+                        (x_of(ind, start.col as usize), x_of(ind, to))
+                    }
+                    _ => {
+                        let from = if ind == start.row as usize {
+                            start.col as usize
+                        } else {
+                            0
+                        };
+                        let to = if ind == end.row as usize {
+                            let under =
+                                (ln.get(end.col as usize..)).and_then(|rest| rest.chars().next());
+                            end.col as usize + under.map_or(1, char::len_utf8)
+                        } else {
+                            ln.len() + 1
+                        };
+                        (x_of(ind, from), x_of(ind, to))
+                    }
+                };
+                if let Some(highlight) = span(row, from, to) {
+                    buf.set_style(highlight, Style::default().reversed());
+                }
+            }
+        }
+
+        // the match pending confirmation
+        if let Some(confirm) = self.app.confirm.as_ref().filter(|_| self.focused) {
+            let (from, to) = (confirm.col, confirm.col + confirm.sub.pattern.len());
+            let (from, to) = (x_of(confirm.row, from), x_of(confirm.row, to));
+            let row = confirm.row.checked_sub(self.view_shift.row as usize);
+            if let Some(highlight) = row.and_then(|row| span(row as u16, from, to)) {
+                buf.set_style(highlight, Style::default().reversed());
             }
         }
     }
@@ -551,6 +4302,480 @@ fn init_log() -> Result<(), AppError> {
     Ok(())
 }
 
+fn expand_tilde(path: &str) -> PathBuf {
+    let path = if path == "~" { "~/" } else { path };
+    match path.strip_prefix("~/") {
+        Some(rest) => match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(rest),
+            None => PathBuf::from(path),
+        },
+        None => PathBuf::from(path),
+    }
+}
+
+/// `text` with its line breaks, which a terminal may send as carriage returns, as newlines.
+fn line_breaks(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// What `key` comes to in Normal mode after the keys typed so far, `keys`, along with the keys
+/// left waiting for more once it is acted on. A key whose meaning hangs on the cursor, the view
+/// or the windows comes to `None`, leaving `keys` as they are for
+/// [`App::handle_event_in_context`] to decide.
+fn resolve(keys: PendingInput, key: KeyEvent) -> (PendingInput, Option<AppAction>) {
+    let action = match keys.pending {
+        Some(pending) => resolve_pending(keys, pending, key),
+        None => resolve_key(keys, key),
+    };
+    match action {
+        Some(action) => (keys.after(&action), Some(action)),
+        None => (keys, None),
+    }
+}
+
+/// What `key` does or starts in Normal mode, after the count and the register typed if any.
+fn resolve_key(keys: PendingInput, key: KeyEvent) -> Option<AppAction> {
+    let count = keys.count;
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let action = match key.code {
+        KeyCode::Char('w') if ctrl => AppAction::Pending(Pending::Window),
+        KeyCode::Char('g') if ctrl => AppAction::FileInfo,
+        KeyCode::Char(ch @ ('f' | 'b')) if ctrl => AppAction::Page {
+            down: ch == 'f',
+            count: count.unwrap_or(1),
+        },
+        KeyCode::PageDown | KeyCode::PageUp => AppAction::Page {
+            down: key.code == KeyCode::PageDown,
+            count: count.unwrap_or(1),
+        },
+        KeyCode::Char(ch @ ('d' | 'u')) if ctrl => AppAction::HalfPage {
+            down: ch == 'd',
+            lines: count,
+        },
+        KeyCode::Char('v') if ctrl => AppAction::EnterMode(AppMode::VisualBlock),
+        KeyCode::Char('h' | 'j' | 'k' | 'l' | ';' | ',' | 'H' | 'M' | 'L' | 'q')
+        | KeyCode::Left
+        | KeyCode::Down
+        | KeyCode::Up
+        | KeyCode::Right => return None,
+        KeyCode::Char(ch @ '1'..='9') => keys.push_count(ch),
+        KeyCode::Char('0') if count.is_some() => keys.push_count('0'),
+        code @ (KeyCode::Char('w' | 'b' | 'e' | '0' | '^' | '$' | 'G' | '+' | '-')
+        | KeyCode::Enter
+        | KeyCode::Home
+        | KeyCode::End) => motion_action(None, key_motion(code, count)?, count),
+        KeyCode::Char('g') => AppAction::Pending(Pending::G(None, count)),
+        KeyCode::Char(cmd @ ('f' | 'F' | 't' | 'T')) => {
+            AppAction::Pending(Pending::Find(None, count, cmd))
+        }
+        KeyCode::Char(ch @ ('n' | 'N')) => AppAction::SearchNext {
+            reverse: ch == 'N',
+            count: count.unwrap_or(1),
+        },
+        KeyCode::Char('m') => AppAction::Pending(Pending::Mark),
+        KeyCode::Char('z') => AppAction::Pending(Pending::Z),
+        KeyCode::Char(cmd @ ('`' | '\'')) => AppAction::Pending(Pending::ToMark(None, cmd)),
+        KeyCode::Char('x') | KeyCode::Delete => AppAction::DeleteUnder(count.unwrap_or(1)),
+        KeyCode::Char('X') => AppAction::DeleteBefore(count.unwrap_or(1)),
+        KeyCode::Char('D') => AppAction::DeleteToEnd,
+        KeyCode::Char('s') => AppAction::SubstituteChars(count.unwrap_or(1)),
+        KeyCode::Char('S') => AppAction::OperateLines(Operator::Change, count.unwrap_or(1)),
+        KeyCode::Char('Y') => AppAction::OperateLines(Operator::Yank, count.unwrap_or(1)),
+        KeyCode::Char('J') => AppAction::JoinLines(count.unwrap_or(2)),
+        KeyCode::Char('u') => AppAction::Undo(count.unwrap_or(1)),
+        KeyCode::Char('.') => AppAction::Repeat(count),
+        KeyCode::Char('"') => AppAction::Pending(Pending::Register),
+        KeyCode::Char('@') => AppAction::Pending(Pending::Replay(count)),
+        KeyCode::Char('p') => AppAction::Put {
+            before: false,
+            count: count.unwrap_or(1),
+        },
+        KeyCode::Char('P') => AppAction::Put {
+            before: true,
+            count: count.unwrap_or(1),
+        },
+        KeyCode::Char('o') => AppAction::OpenBelow(count.unwrap_or(1)),
+        KeyCode::Char('O') => AppAction::OpenAbove(count.unwrap_or(1)),
+        code @ KeyCode::Char('d' | 'c' | 'y' | '>' | '<') => {
+            AppAction::Pending(Pending::Operator(key_operator(code)?, count))
+        }
+        KeyCode::Char('C') => {
+            AppAction::Operate(Operator::Change, Motion::LineEnd, count.unwrap_or(1))
+        }
+        KeyCode::Char('i') => AppAction::Insert(count.unwrap_or(1)),
+        KeyCode::Char('R') => AppAction::EnterMode(AppMode::Replace),
+        KeyCode::Char('v') => AppAction::EnterMode(AppMode::Visual),
+        KeyCode::Char('V') => AppAction::EnterMode(AppMode::VisualLine),
+        KeyCode::Char('a') => AppAction::Append(count.unwrap_or(1)),
+        KeyCode::Char('A') => AppAction::AppendAtEnd(count.unwrap_or(1)),
+        KeyCode::Char('I') => AppAction::InsertAtIndent(count.unwrap_or(1)),
+        KeyCode::Char(':') => AppAction::EnterMode(AppMode::Command),
+        KeyCode::Char(ch @ ('/' | '?')) => AppAction::EnterMode(AppMode::Search {
+            backward: ch == '?',
+        }),
+        _ => AppAction::None,
+    };
+    Some(action)
+}
+
+/// What `key` makes of the multi-key command started with `pending`.
+fn resolve_pending(keys: PendingInput, pending: Pending, key: KeyEvent) -> Option<AppAction> {
+    let action = match pending {
+        Pending::Window => return None,
+        Pending::Operator(op, before) => {
+            let count = keys.combined_count(before);
+            match key.code {
+                KeyCode::Char(ch @ '1'..='9') => keys.push_count(ch),
+                KeyCode::Char('0') if keys.count.is_some() => keys.push_count('0'),
+                // a doubled operator acts on whole lines
+                // as does a case operator followed by its last key, `guu` or `gUU`
+                code if key_operator(code) == Some(op) || case_operator(code) == Some(op) => {
+                    AppAction::OperateLines(op, count.unwrap_or(1))
+                }
+                KeyCode::Char('g') => AppAction::Pending(Pending::G(Some(op), count)),
+                KeyCode::Char(cmd @ ('f' | 'F' | 't' | 'T')) => {
+                    AppAction::Pending(Pending::Find(Some(op), count, cmd))
+                }
+                KeyCode::Char(cmd @ ('`' | '\'')) => {
+                    AppAction::Pending(Pending::ToMark(Some(op), cmd))
+                }
+                KeyCode::Char(kind @ ('i' | 'a')) => {
+                    AppAction::Pending(Pending::Object(Some(op), count, kind == 'a'))
+                }
+                KeyCode::Char(';' | ',' | 'H' | 'M' | 'L') => return None,
+                code => match key_motion(code, count) {
+                    Some(motion) => motion_action(Some(op), motion, count),
+                    None => AppAction::None,
+                },
+            }
+        }
+        Pending::G(op, count) => match key.code {
+            KeyCode::Char('g') => {
+                let motion = Motion::ToLine(Some(count.unwrap_or(1)));
+                motion_action(op, motion, None)
+            }
+            KeyCode::Char('v') if op.is_none() => AppAction::Reselect,
+            code => match (op, case_operator(code)) {
+                (None, Some(case)) => AppAction::Pending(Pending::Operator(case, count)),
+                // `gugu` and `gUgU` act on lines like `guu` and `gUU`
+                (Some(op), Some(case)) if op == case => {
+                    AppAction::OperateLines(op, count.unwrap_or(1))
+                }
+                _ => AppAction::None,
+            },
+        },
+        Pending::Find(op, count, cmd) => match key.code {
+            KeyCode::Char(ch) => motion_action(op, Motion::Find(Find::new(cmd, ch)), count),
+            _ => AppAction::None,
+        },
+        Pending::Register => match key.code {
+            KeyCode::Char(name) if Registers::is_readable(name) => AppAction::SelectRegister(name),
+            _ => AppAction::None,
+        },
+        Pending::Object(op, count, around) => {
+            let (open, close) = match key.code {
+                KeyCode::Char('w') => {
+                    let object = TextObject::Word { around };
+                    return Some(AppAction::Object(op, object, count.unwrap_or(1)));
+                }
+                KeyCode::Char(quote @ ('"' | '\'' | '`')) => {
+                    let object = TextObject::Quote { quote, around };
+                    return Some(AppAction::Object(op, object, count.unwrap_or(1)));
+                }
+                KeyCode::Char('(' | ')' | 'b') => ('(', ')'),
+                KeyCode::Char('{' | '}' | 'B') => ('{', '}'),
+                KeyCode::Char('[' | ']') => ('[', ']'),
+                KeyCode::Char('<' | '>') => ('<', '>'),
+                _ => return Some(AppAction::None),
+            };
+            let object = TextObject::Bracket {
+                open,
+                close,
+                around,
+            };
+            AppAction::Object(op, object, count.unwrap_or(1))
+        }
+        Pending::Z => match key.code {
+            KeyCode::Char('t') => AppAction::PlaceView(ScreenRow::Top),
+            KeyCode::Char('z') => AppAction::PlaceView(ScreenRow::Middle),
+            KeyCode::Char('b') => AppAction::PlaceView(ScreenRow::Bottom),
+            _ => AppAction::None,
+        },
+        Pending::Mark => match key.code {
+            KeyCode::Char(name @ ('a'..='z' | '`' | '\'')) => AppAction::SetMark(mark_name(name)),
+            _ => AppAction::None,
+        },
+        Pending::ToMark(op, cmd) => match key.code {
+            KeyCode::Char(name @ ('a'..='z' | '`' | '\'' | '.')) => AppAction::ToMark {
+                op,
+                name: mark_name(name),
+                linewise: cmd == '\'',
+            },
+            _ => AppAction::None,
+        },
+        Pending::Record => match key.code {
+            KeyCode::Char(name) if name.is_ascii_alphabetic() => AppAction::StartRecording(name),
+            _ => AppAction::None,
+        },
+        Pending::Replay(count) => match key.code {
+            KeyCode::Char(name) if name == '@' || Registers::is_readable(name) => {
+                AppAction::Replay(name, count.unwrap_or(1))
+            }
+            _ => AppAction::None,
+        },
+    };
+    Some(action)
+}
+
+/// The keys of a Normal mode command with the counts typed in them, before the operator and
+/// after it alike, dropped for `count` to go before the command instead, after its register.
+fn with_count(keys: &[KeyEvent], count: usize) -> Vec<KeyEvent> {
+    let mut out = Vec::new();
+    let mut command = None;
+    let mut state = PendingInput::default();
+    for (ind, key) in keys.iter().enumerate() {
+        let (next, action) = resolve(state, *key);
+        match action {
+            Some(AppAction::Count(_)) => {}
+            Some(AppAction::Pending(Pending::Register) | AppAction::SelectRegister(_)) => {
+                out.push(*key)
+            }
+            Some(AppAction::Pending(_)) => {
+                command.get_or_insert(out.len());
+                out.push(*key);
+            }
+            // the rest, once the command is complete, is typed in whatever mode it leads to
+            _ => {
+                command.get_or_insert(out.len());
+                out.extend_from_slice(&keys[ind..]);
+                break;
+            }
+        }
+        state = next;
+    }
+    let at = command.unwrap_or(out.len());
+    let digits = count.to_string();
+    let digits = digits
+        .chars()
+        .map(|ch| KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE));
+    out.splice(at..at, digits);
+    out
+}
+
+/// Move by `motion`, or apply `op` over it.
+fn motion_action(op: Option<Operator>, motion: Motion, count: Option<usize>) -> AppAction {
+    match op {
+        Some(op) => AppAction::Operate(op, motion, count.unwrap_or(1)),
+        None => AppAction::Motion(motion, count.unwrap_or(1)),
+    }
+}
+
+/// The motion `code` stands for, given the count typed before it.
+fn key_motion(code: KeyCode, count: Option<usize>) -> Option<Motion> {
+    match code {
+        KeyCode::Char('w') => Some(Motion::WordStart),
+        KeyCode::Char('e') => Some(Motion::WordEnd),
+        KeyCode::Char('b') => Some(Motion::WordBack),
+        KeyCode::Char('0') | KeyCode::Home => Some(Motion::LineStart),
+        KeyCode::Char('^') => Some(Motion::FirstNonBlank),
+        KeyCode::Char('$') | KeyCode::End => Some(Motion::LineEnd),
+        KeyCode::Char('G') => Some(Motion::ToLine(count)),
+        KeyCode::Char('j') | KeyCode::Down => Some(Motion::Lines { down: true }),
+        KeyCode::Char('k') | KeyCode::Up => Some(Motion::Lines { down: false }),
+        KeyCode::Char('+') | KeyCode::Enter => Some(Motion::NonBlankLines { down: true }),
+        KeyCode::Char('-') => Some(Motion::NonBlankLines { down: false }),
+        _ => None,
+    }
+}
+
+/// The mark `name` stands for, `'` being another name for `` ` ``.
+fn mark_name(name: char) -> char {
+    match name {
+        '\'' => '`',
+        name => name,
+    }
+}
+
+/// The operator `code` stands for.
+fn key_operator(code: KeyCode) -> Option<Operator> {
+    match code {
+        KeyCode::Char('d') => Some(Operator::Delete),
+        KeyCode::Char('c') => Some(Operator::Change),
+        KeyCode::Char('y') => Some(Operator::Yank),
+        KeyCode::Char('>') => Some(Operator::Indent),
+        KeyCode::Char('<') => Some(Operator::Dedent),
+        _ => None,
+    }
+}
+
+/// The case operator `code` stands for after `g`.
+fn case_operator(code: KeyCode) -> Option<Operator> {
+    match code {
+        KeyCode::Char('u') => Some(Operator::Case(Case::Lower)),
+        KeyCode::Char('U') => Some(Operator::Case(Case::Upper)),
+        KeyCode::Char('~') => Some(Operator::Case(Case::Toggle)),
+        _ => None,
+    }
+}
+
+/// Turn the argument of `:normal` into key presses, with special keys written like `<Esc>`,
+/// `<CR>` or `<C-w>`.
+fn parse_keys(text: &str) -> Vec<KeyEvent> {
+    let mut keys = Vec::new();
+    let mut rest = text;
+    while let Some(ch) = rest.chars().next() {
+        let special = (rest.strip_prefix('<'))
+            .and_then(|name| name.split_once('>'))
+            .and_then(|(name, after)| Some((parse_key_name(name)?, after)));
+        if let Some((key, after)) = special {
+            keys.push(key);
+            rest = after;
+        } else {
+            keys.push(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE));
+            rest = &rest[ch.len_utf8()..];
+        }
+    }
+    keys
+}
+
+/// How `key` is written in the text of a macro, the way [`parse_keys`] reads it back.
+fn key_text(key: KeyEvent) -> Option<String> {
+    let name = match key.code {
+        KeyCode::Char(ch) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            return Some(format!("<C-{}>", ch))
+        }
+        KeyCode::Char('<') => "lt",
+        KeyCode::Char(ch) => return Some(ch.to_string()),
+        KeyCode::Esc => "Esc",
+        KeyCode::Enter => "CR",
+        KeyCode::Backspace => "BS",
+        KeyCode::Delete => "Del",
+        KeyCode::Tab => "Tab",
+        KeyCode::BackTab => "S-Tab",
+        KeyCode::Up => "Up",
+        KeyCode::Down => "Down",
+        KeyCode::Left => "Left",
+        KeyCode::Right => "Right",
+        KeyCode::Home => "Home",
+        KeyCode::End => "End",
+        KeyCode::PageUp => "PageUp",
+        KeyCode::PageDown => "PageDown",
+        _ => return None,
+    };
+    Some(format!("<{}>", name))
+}
+
+fn parse_key_name(name: &str) -> Option<KeyEvent> {
+    let code = match name.to_ascii_lowercase().as_str() {
+        "esc" => KeyCode::Esc,
+        "cr" | "enter" | "return" => KeyCode::Enter,
+        "bs" => KeyCode::Backspace,
+        "del" => KeyCode::Delete,
+        "tab" => KeyCode::Tab,
+        "s-tab" => KeyCode::BackTab,
+        "space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        lower => {
+            let mut chars = lower.strip_prefix("c-")?.chars();
+            let (Some(ch), None) = (chars.next(), chars.next()) else {
+                return None;
+            };
+            return Some(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::CONTROL));
+        }
+    };
+    Some(KeyEvent::new(code, KeyModifiers::NONE))
+}
+
+/// The count given as the argument of a command like `:undo 3`, `1` if there is none.
+fn count_arg(arg: &str) -> Result<usize, CmdError> {
+    if arg.is_empty() {
+        return Ok(1);
+    }
+    arg.parse()
+        .map_err(|_| CmdError::InvalidArg(arg.to_string()))
+}
+
+/// The register named by the argument of a command like `:d x`, if any.
+fn register_arg(arg: &str) -> Result<Option<char>, CmdError> {
+    let mut chars = arg.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => Ok(None),
+        (Some(name), None) if Registers::is_writable(name) => Ok(Some(name)),
+        _ => Err(CmdError::InvalidArg(arg.to_string())),
+    }
+}
+
+fn substitution_result(pattern: &str, subs: usize, lines: usize) -> CmdResult {
+    if subs == 0 {
+        return Err(CmdError::PatternNotFound(pattern.to_string()));
+    }
+    Ok(Some(format!("{} substitutions on {} lines", subs, lines)))
+}
+
+/// List the paths that `partial` can be completed to, directories ending with a `/`.
+fn complete_path(partial: &str) -> Vec<String> {
+    let (dir, prefix) = match partial.rfind('/') {
+        Some(ind) => partial.split_at(ind + 1),
+        None => ("", partial),
+    };
+    let read_from = if dir.is_empty() {
+        PathBuf::from(".")
+    } else {
+        expand_tilde(dir)
+    };
+    let entries = match fs::read_dir(read_from) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut candidates: Vec<_> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.path().is_dir();
+            Some(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+/// The help text laid out as lines, a heading above each section.
+fn help_lines() -> Vec<Line<'static>> {
+    let mut lines = vec![line!["ViX - A Vi-like Text Editor"].bold().centered()];
+    for (title, entries) in HELP {
+        lines.push(line![]);
+        lines.push(line![*title].bold());
+        for (keys, description) in entries.iter() {
+            lines.push(Line::from(format!("  {:<16}{}", keys, description)));
+        }
+    }
+    lines
+}
+
+fn help_widget(scroll: u16) -> impl Widget {
+    Paragraph::new(help_lines()).scroll((scroll, 0)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Help (j/k, Ctrl-d/Ctrl-u to scroll, q to close)"),
+    )
+}
+
+fn help_rect(area: Rect) -> Rect {
+    centered_rect(area, 70, 70)
+}
+
 // https://ratatui.rs/recipes/layout/center-a-rect/
 fn centered_rect(r: Rect, percent_x: u16, percent_y: u16) -> Rect {
     let popup_layout = Layout::default()
@@ -571,3 +4796,262 @@ fn centered_rect(r: Rect, percent_x: u16, percent_y: u16) -> Rect {
         ])
         .split(popup_layout[1])[1]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The keys left waiting after `keys` are typed in Normal mode, and what the last one does.
+    fn resolve_keys(keys: &str) -> (PendingInput, Option<AppAction>) {
+        let mut state = (PendingInput::default(), None);
+        for key in parse_keys(keys) {
+            state = resolve(state.0, key);
+        }
+        state
+    }
+
+    /// An editor on `text` in a window of a usual size, with `keys` typed into it.
+    fn app_with(text: &str, keys: &str) -> App {
+        let mut app = App {
+            buffers: vec![DocBuffer::new(1, Document::from_text(text))],
+            size: Rect::new(0, 0, 80, 24),
+            popup: None,
+            ..App::default()
+        };
+        type_keys(&mut app, keys);
+        app
+    }
+
+    fn type_keys(app: &mut App, keys: &str) {
+        for key in parse_keys(keys) {
+            app.input(Event::Key(key)).unwrap();
+        }
+    }
+
+    /// Check that each of `cases` completes a command, leaving no keys waiting.
+    fn check_complete<'a>(cases: impl IntoIterator<Item = (&'a str, AppAction)>) {
+        for (keys, expected) in cases {
+            let done = (PendingInput::default(), Some(expected));
+            assert_eq!(resolve_keys(keys), done, "{}", keys);
+        }
+    }
+
+    #[test]
+    fn counts_multiply_across_an_operator() {
+        let (keys, action) = resolve_keys("2d3");
+        assert_eq!(action, Some(AppAction::Count(3)));
+        assert_eq!(
+            keys.pending,
+            Some(Pending::Operator(Operator::Delete, Some(2)))
+        );
+        let (keys, action) = resolve_keys("2d3w");
+        assert_eq!(
+            action,
+            Some(AppAction::Operate(Operator::Delete, Motion::WordStart, 6))
+        );
+        assert!(keys.is_empty());
+    }
+
+    #[test]
+    fn register_is_kept_until_the_command_is_done() {
+        let (keys, _) = resolve_keys("\"a2d3");
+        assert_eq!(keys.register, Some('a'));
+        assert_eq!(keys.count, Some(3));
+        let (keys, action) = resolve_keys("\"a2d3w");
+        assert_eq!(
+            action,
+            Some(AppAction::Operate(Operator::Delete, Motion::WordStart, 6))
+        );
+        assert!(keys.is_empty());
+        // a digit right after `"` names a register, not a count
+        let (keys, action) = resolve_keys("\"2d3");
+        assert_eq!(keys.register, Some('2'));
+        assert_eq!(
+            keys.pending,
+            Some(Pending::Operator(Operator::Delete, None))
+        );
+        assert_eq!(action, Some(AppAction::Count(3)));
+    }
+
+    #[test]
+    fn doubled_operators_act_on_lines() {
+        let lines = AppAction::OperateLines;
+        check_complete([
+            ("dd", lines(Operator::Delete, 1)),
+            ("3dd", lines(Operator::Delete, 3)),
+            ("d3d", lines(Operator::Delete, 3)),
+            ("yy", lines(Operator::Yank, 1)),
+            ("gUU", lines(Operator::Case(Case::Upper), 1)),
+            ("gugu", lines(Operator::Case(Case::Lower), 1)),
+        ]);
+    }
+
+    #[test]
+    fn multi_key_motions() {
+        let find = |cmd, ch| Motion::Find(Find::new(cmd, ch));
+        check_complete([
+            ("gg", AppAction::Motion(Motion::ToLine(Some(1)), 1)),
+            ("5gg", AppAction::Motion(Motion::ToLine(Some(5)), 1)),
+            (
+                "dgg",
+                AppAction::Operate(Operator::Delete, Motion::ToLine(Some(1)), 1),
+            ),
+            ("fx", AppAction::Motion(find('f', 'x'), 1)),
+            ("2Tx", AppAction::Motion(find('T', 'x'), 2)),
+            (
+                "d2tx",
+                AppAction::Operate(Operator::Delete, find('t', 'x'), 2),
+            ),
+        ]);
+    }
+
+    #[test]
+    fn esc_and_unknown_keys_cancel() {
+        let keys = [
+            "d<Esc>", "2d<Esc>", "\"a<Esc>", "g<Esc>", "f<Esc>", "dZ", "zq", "g%", "\"!",
+        ];
+        check_complete(keys.map(|keys| (keys, AppAction::None)));
+    }
+
+    #[test]
+    fn keys_needing_the_editor_are_left_to_it() {
+        let (keys, action) = resolve_keys("2d;");
+        assert_eq!(action, None);
+        assert_eq!(
+            keys.pending,
+            Some(Pending::Operator(Operator::Delete, Some(2)))
+        );
+        assert_eq!(resolve_keys("3j").1, None);
+        assert_eq!(resolve_keys("<C-w>l").1, None);
+    }
+
+    #[test]
+    fn noh_keeps_the_pattern() {
+        let mut app = app_with("foo\nbar foo\nfoo", "/foo<CR>");
+        assert!(app.search.highlight);
+        assert_eq!(app.doc_pos(), Position { row: 1, col: 4 });
+        type_keys(&mut app, ":noh<CR>");
+        assert!(!app.search.highlight);
+        assert_eq!(app.search.pattern.as_deref(), Some("foo"));
+        type_keys(&mut app, "n");
+        assert_eq!(app.doc_pos(), Position { row: 2, col: 0 });
+        // searching again brings the highlighting back
+        assert!(app.search.highlight);
+    }
+
+    #[test]
+    fn repeat_count_replaces_every_count() {
+        let text = "a b c d e f g h i j k l m n o p";
+        let mut app = app_with(text, "\"a3dw");
+        type_keys(&mut app, "2.");
+        assert_eq!(app.doc().get_line(0), Some("f g h i j k l m n o p"));
+        let register = app.registers.get('a').cloned();
+        assert_eq!(register, Some(Register::Charwise("d e ".to_string())));
+
+        let mut app = app_with(text, "2d3w");
+        type_keys(&mut app, "4.");
+        assert_eq!(app.doc().get_line(0), Some("k l m n o p"));
+        // a digit after `"` names a register rather than counting
+        assert_eq!(with_count(&parse_keys("\"2p"), 3), parse_keys("\"23p"));
+    }
+
+    #[test]
+    fn normal_types_trailing_spaces() {
+        let app = app_with("foo\nbar", ":normal A  <CR>");
+        assert_eq!(app.doc().get_line(0), Some("foo  "));
+        let app = app_with("foo\nbar", ":%norm i <CR>");
+        assert_eq!(app.doc().get_line(0), Some(" foo"));
+        assert_eq!(app.doc().get_line(1), Some(" bar"));
+    }
+
+    #[test]
+    fn buffer_list_takes_numbers_of_several_digits() {
+        let mut app = app_with("", "");
+        let texts = (2..=12).map(|id| DocBuffer::new(id, Document::from_text(&id.to_string())));
+        app.buffers.extend(texts);
+        type_keys(&mut app, ":ls<CR>12");
+        assert_eq!(app.popup, Some(Popup::Buffers { number: Some(12) }));
+        type_keys(&mut app, "<CR>");
+        assert_eq!(
+            (app.popup.clone(), app.doc().get_line(0)),
+            (None, Some("12"))
+        );
+        type_keys(&mut app, ":ls<CR>13<BS>1<CR>");
+        assert_eq!(
+            (app.popup.clone(), app.doc().get_line(0)),
+            (None, Some("11"))
+        );
+        type_keys(&mut app, ":ls<CR>2<Esc>");
+        assert_eq!(
+            (app.popup.clone(), app.doc().get_line(0)),
+            (None, Some("11"))
+        );
+    }
+
+    #[test]
+    fn change_lines_on_an_empty_buffer() {
+        for keys in ["ccfoo<Esc>", "Sfoo<Esc>", "dd:%d<CR>ccfoo<Esc>"] {
+            let app = app_with("", keys);
+            assert_eq!(app.doc().get_line(0), Some("foo"), "{}", keys);
+        }
+    }
+
+    #[test]
+    fn quitting_refuses_with_hidden_changes() {
+        for quit in [":q<CR>", ":x<CR>", ":qa<CR>"] {
+            let mut app = app_with("foo", "x");
+            app.buffers
+                .push(DocBuffer::new(2, Document::from_text("bar")));
+            type_keys(&mut app, ":bn<CR>");
+            assert_eq!(app.doc().get_line(0), Some("bar"));
+            type_keys(&mut app, quit);
+            assert!(app.running, "{}", quit);
+        }
+        let mut app = app_with("foo", "x");
+        app.buffers
+            .push(DocBuffer::new(2, Document::from_text("bar")));
+        type_keys(&mut app, ":bn<CR>:qa!<CR>");
+        assert!(!app.running);
+    }
+
+    #[test]
+    fn tabs_are_drawn_out_to_the_tab_stop() {
+        let app = app_with("\tab\nx\ty", "$");
+        assert_eq!(app.screen_col(app.cursor, app.view_shift), 9);
+        let area = Rect::new(0, 0, 12, 2);
+        let mut buf = Buffer::empty(area);
+        let view = DocView {
+            app: &app,
+            doc: app.doc(),
+            cursor: app.cursor,
+            view_shift: app.view_shift,
+            focused: true,
+        };
+        view.render(area, &mut buf);
+        assert_eq!(
+            buf,
+            Buffer::with_lines(vec!["        ab  ", "x       y   "])
+        );
+
+        // a line wider on screen than in bytes still scrolls to keep the cursor in view
+        let app = app_with(&format!("{}x", "\t".repeat(12)), "$");
+        let col = app.screen_col(app.cursor, app.view_shift);
+        assert!(
+            app.view_shift.col > 0 && col < 80,
+            "{:?} at {}",
+            app.view_shift,
+            col
+        );
+        assert_eq!(app.doc_pos(), Position { row: 0, col: 12 });
+    }
+
+    #[test]
+    fn every_command_completes() {
+        let app = app_with("foo", ":sub<Tab>");
+        assert_eq!(app.cmd, "substitute");
+        let app = app_with("foo bar", ":substitute/o/0/g<CR>");
+        assert_eq!(app.doc().get_line(0), Some("f00 bar"));
+        let app = app_with("foo", ":regi<Tab>");
+        assert_eq!(app.cmd, "registers");
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::register::Register;
+
+#[derive(Debug, Error)]
+#[error("Clipboard unavailable: {0}")]
+pub struct ClipboardError(#[from] arboard::Error);
+
+/// The system clipboard behind the `"+` and `"*` registers, connected to on first use so that a
+/// session without one (e.g. over SSH) only fails once it is asked for.
+#[derive(Default)]
+pub struct Clipboard {
+    system: Option<arboard::Clipboard>,
+    /// What was last copied, so that whole lines still paste as lines when the clipboard holds
+    /// that same text.
+    copied: Option<Register>,
+}
+
+impl fmt::Debug for Clipboard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Clipboard")
+            .field("connected", &self.system.is_some())
+            .field("copied", &self.copied)
+            .finish()
+    }
+}
+
+impl Clipboard {
+    fn system(&mut self) -> Result<&mut arboard::Clipboard, ClipboardError> {
+        if self.system.is_none() {
+            self.system = Some(arboard::Clipboard::new()?);
+        }
+        Ok(self.system.as_mut().unwrap())
+    }
+
+    /// Put `content` on the clipboard, lines ending with a newline each.
+    pub fn copy(&mut self, content: &Register) -> Result<(), ClipboardError> {
+        self.system()?.set_text(text(content))?;
+        self.copied = Some(content.clone());
+        Ok(())
+    }
+
+    /// The text on the clipboard, if there is any: what was last copied if it is still there,
+    /// otherwise whole lines if it ends with a newline.
+    pub fn paste(&mut self) -> Result<Option<Register>, ClipboardError> {
+        let pasted = match self.system()?.get_text() {
+            Ok(pasted) => pasted.replace("\r\n", "\n"),
+            Err(arboard::Error::ContentNotAvailable) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        if let Some(copied) = self.copied.as_ref().filter(|reg| text(reg) == pasted) {
+            return Ok(Some(copied.clone()));
+        }
+        Ok(Some(Register::from_text(pasted)))
+    }
+}
+
+/// How `content` reads as plain text.
+fn text(content: &Register) -> String {
+    match content {
+        Register::Linewise(lines) => lines.iter().map(|ln| format!("{}\n", ln)).collect(),
+        Register::Charwise(text) => text.clone(),
+        Register::Blockwise(lines) => lines.join("\n"),
+    }
+}
@@ -0,0 +1,423 @@
+use thiserror::Error;
+
+/// Names of the commands understood in Command mode, in the order Tab completion offers them.
+pub const COMMANDS: &[&str] = &[
+    "bd",
+    "bdelete",
+    "bn",
+    "bnext",
+    "bp",
+    "bprevious",
+    "buffers",
+    "cd",
+    "co",
+    "command",
+    "copy",
+    "d",
+    "delete",
+    "e",
+    "earlier",
+    "edit",
+    "exit",
+    "f",
+    "file",
+    "g",
+    "global",
+    "h",
+    "help",
+    "k",
+    "later",
+    "ls",
+    "m",
+    "mark",
+    "marks",
+    "move",
+    "noh",
+    "nohlsearch",
+    "normal",
+    "pwd",
+    "q",
+    "qa",
+    "qall",
+    "quit",
+    "quitall",
+    "r",
+    "read",
+    "redo",
+    "reg",
+    "registers",
+    "retab",
+    "s",
+    "set",
+    "sp",
+    "split",
+    "substitute",
+    "syntax",
+    "t",
+    "undo",
+    "v",
+    "vglobal",
+    "vs",
+    "vsp",
+    "vsplit",
+    "w",
+    "wa",
+    "wall",
+    "wq",
+    "wqa",
+    "wqall",
+    "write",
+    "x",
+    "xa",
+    "xall",
+    "xit",
+    "y",
+    "yank",
+];
+
+/// Whether the argument of command `name` is a path, and thus completed as one.
+pub fn takes_path(name: &str) -> bool {
+    let name = name.trim_end_matches('!');
+    matches!(name, "cd" | "e" | "edit" | "r" | "read" | "w" | "write")
+        || matches!(name, "sp" | "split" | "vs" | "vsp" | "vsplit")
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Substitute {
+    pub pattern: String,
+    pub replacement: String,
+    pub global: bool,
+    pub confirm: bool,
+}
+
+/// A `:g/pattern/cmd` command, running `cmd` on every line containing `pattern`, or with `invert`
+/// (`:g!` and `:v`) on every line that does not.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Global {
+    pub pattern: String,
+    pub command: String,
+    pub invert: bool,
+}
+
+/// A single line address of an ex range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Address {
+    /// `.`, the cursor line
+    Current,
+    /// `$`, the last line
+    Last,
+    /// `N`, a 1-based line number
+    Line(usize),
+}
+
+/// An ex range such as `%`, `N,M` or `.,$` put in front of a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: Address,
+    pub end: Address,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CommandError {
+    #[error("Invalid flag `{0}`")]
+    InvalidFlag(char),
+    #[error("Trailing characters: {0}")]
+    TrailingChars(String),
+    #[error("Invalid address: {0}")]
+    InvalidAddress(String),
+}
+
+impl Substitute {
+    /// Parse the `/pattern/replacement/flags` part of a `:s` command, `body` starting with the
+    /// delimiter.
+    pub fn parse(body: &str) -> Result<Self, CommandError> {
+        let mut chars = body.chars();
+        let delim = chars.next().unwrap_or('/');
+        let mut parts = split_escaped(chars.as_str(), delim).into_iter();
+
+        let pattern = parts.next().unwrap_or_default();
+        let replacement = parts.next().unwrap_or_default();
+        let flags = parts.next().unwrap_or_default();
+        let rest: Vec<_> = parts.collect();
+        if !rest.is_empty() {
+            return Err(CommandError::TrailingChars(rest.join(&delim.to_string())));
+        }
+
+        let mut global = false;
+        let mut confirm = false;
+        for flag in flags.chars() {
+            match flag {
+                'g' => global = true,
+                'c' => confirm = true,
+                ch => return Err(CommandError::InvalidFlag(ch)),
+            }
+        }
+
+        Ok(Self {
+            pattern,
+            replacement,
+            global,
+            confirm,
+        })
+    }
+}
+
+impl Global {
+    /// Parse `cmd` as a `:g`, `:g!` or `:v` command, if it is one.
+    pub fn parse(cmd: &str) -> Option<Self> {
+        let forms = [
+            ("global!", true),
+            ("g!", true),
+            ("global", false),
+            ("g", false),
+            ("vglobal", true),
+            ("v", true),
+        ];
+        let (body, invert) = forms.iter().find_map(|(name, invert)| {
+            let body = cmd.strip_prefix(name)?;
+            body.starts_with(is_delimiter).then_some((body, *invert))
+        })?;
+
+        let mut chars = body.chars();
+        let delim = chars.next().unwrap_or('/');
+        let mut pattern = String::new();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '\\' if chars.clone().next() == Some(delim) => {
+                    pattern.push(delim);
+                    chars.next();
+                }
+                ch if ch == delim => break,
+                ch => pattern.push(ch),
+            }
+        }
+
+        Some(Self {
+            pattern,
+            command: chars.as_str().trim().to_string(),
+            invert,
+        })
+    }
+}
+
+impl Address {
+    /// Parse `arg` as nothing but an address, such as the destination of `:m`.
+    pub fn parse_only(arg: &str) -> Result<Self, CommandError> {
+        match Self::parse(arg) {
+            Some((addr, "")) => Ok(addr),
+            Some((_, rest)) => Err(CommandError::TrailingChars(rest.to_string())),
+            None => Err(CommandError::InvalidAddress(arg.to_string())),
+        }
+    }
+
+    fn parse(cmd: &str) -> Option<(Self, &str)> {
+        if let Some(rest) = cmd.strip_prefix('.') {
+            return Some((Self::Current, rest));
+        }
+        if let Some(rest) = cmd.strip_prefix('$') {
+            return Some((Self::Last, rest));
+        }
+        let digits = cmd.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            return None;
+        }
+        let line = cmd[..digits].parse().unwrap_or(usize::MAX);
+        Some((Self::Line(line), &cmd[digits..]))
+    }
+
+    /// Resolve into a 0-based row no further than `last`.
+    pub fn resolve(self, current: usize, last: usize) -> usize {
+        match self {
+            Self::Current => current.min(last),
+            Self::Last => last,
+            Self::Line(line) => line.saturating_sub(1).min(last),
+        }
+    }
+
+    /// The row just below this address, for commands that put lines after it: `0` is above the
+    /// first line.
+    pub fn below(self, current: usize, last: usize) -> usize {
+        match self {
+            Self::Line(0) => 0,
+            addr => addr.resolve(current, last) + 1,
+        }
+    }
+}
+
+impl Range {
+    /// The cursor line, the default range of most commands.
+    pub fn current() -> Self {
+        Self {
+            start: Address::Current,
+            end: Address::Current,
+        }
+    }
+
+    /// `%`, every line.
+    pub fn all() -> Self {
+        Self {
+            start: Address::Line(1),
+            end: Address::Last,
+        }
+    }
+
+    /// Split a leading range off `cmd`, returning the rest of the command.
+    pub fn parse(cmd: &str) -> (Option<Self>, &str) {
+        if let Some(rest) = cmd.strip_prefix('%') {
+            return (Some(Self::all()), rest);
+        }
+
+        let Some((start, rest)) = Address::parse(cmd) else {
+            return (None, cmd);
+        };
+        if let Some((end, rest)) = rest.strip_prefix(',').and_then(Address::parse) {
+            return (Some(Self { start, end }), rest);
+        }
+        (Some(Self { start, end: start }), rest)
+    }
+
+    /// Resolve into an ordered, inclusive pair of 0-based rows no further than `last`.
+    pub fn resolve(self, current: usize, last: usize) -> (usize, usize) {
+        let start = self.start.resolve(current, last);
+        let end = self.end.resolve(current, last);
+        (start.min(end), start.max(end))
+    }
+}
+
+/// The `/pattern/replacement/flags` part of `cmd`, if it is a `:s` or `:substitute` command.
+pub fn substitute_body(cmd: &str) -> Option<&str> {
+    let body = cmd
+        .strip_prefix("substitute")
+        .or_else(|| cmd.strip_prefix('s'))?;
+    body.starts_with(is_delimiter).then_some(body)
+}
+
+/// Whether `ch` can separate the parts of a `:s` command.
+fn is_delimiter(ch: char) -> bool {
+    !(ch.is_alphanumeric() || ch.is_whitespace() || matches!(ch, '\\' | '"' | '|'))
+}
+
+/// Split `body` on `delim`, turning every `\<delim>` into a literal `delim`.
+fn split_escaped(body: &str, delim: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = body.chars().peekable();
+    while let Some(ch) = chars.next() {
+        let part = parts.last_mut().unwrap();
+        if ch == '\\' && chars.peek() == Some(&delim) {
+            part.push(delim);
+            chars.next();
+        } else if ch == delim {
+            parts.push(String::new());
+        } else {
+            part.push(ch);
+        }
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_are_sorted() {
+        for pair in COMMANDS.windows(2) {
+            assert!(
+                pair[0] < pair[1],
+                "{:?} comes before {:?}",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    fn sub(pattern: &str, replacement: &str, global: bool, confirm: bool) -> Substitute {
+        Substitute {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            global,
+            confirm,
+        }
+    }
+
+    #[test]
+    fn substitute_parse() {
+        assert_eq!(Substitute::parse("/a/b/"), Ok(sub("a", "b", false, false)));
+        assert_eq!(Substitute::parse("/a/b"), Ok(sub("a", "b", false, false)));
+        assert_eq!(Substitute::parse("/a"), Ok(sub("a", "", false, false)));
+        assert_eq!(Substitute::parse("/a/b/gc"), Ok(sub("a", "b", true, true)));
+        assert_eq!(
+            Substitute::parse("#a/b#c#"),
+            Ok(sub("a/b", "c", false, false))
+        );
+        assert_eq!(
+            Substitute::parse(r"/a\/b/c\//"),
+            Ok(sub("a/b", "c/", false, false))
+        );
+        assert_eq!(
+            Substitute::parse("/a/b/x"),
+            Err(CommandError::InvalidFlag('x'))
+        );
+        let trailing = CommandError::TrailingChars("d".to_string());
+        assert_eq!(Substitute::parse("/a/b/g/d"), Err(trailing));
+    }
+
+    #[test]
+    fn substitute_names() {
+        assert_eq!(substitute_body("s/a/b/"), Some("/a/b/"));
+        assert_eq!(substitute_body("substitute#a#b#"), Some("#a#b#"));
+        assert_eq!(substitute_body("set"), None);
+        assert_eq!(substitute_body("s"), None);
+    }
+
+    #[test]
+    fn global_parse() {
+        let global = |pattern: &str, command: &str, invert| Global {
+            pattern: pattern.to_string(),
+            command: command.to_string(),
+            invert,
+        };
+        assert_eq!(Global::parse("g/foo/d"), Some(global("foo", "d", false)));
+        assert_eq!(
+            Global::parse("global/foo/ s/a/b/ "),
+            Some(global("foo", "s/a/b/", false))
+        );
+        assert_eq!(Global::parse("g!/foo/d"), Some(global("foo", "d", true)));
+        assert_eq!(Global::parse("v#a/b#d"), Some(global("a/b", "d", true)));
+        assert_eq!(Global::parse("vglobal/foo"), Some(global("foo", "", true)));
+        assert_eq!(Global::parse(r"g/a\/b/d"), Some(global("a/b", "d", false)));
+        assert_eq!(Global::parse("goto"), None);
+        assert_eq!(Global::parse("vs"), None);
+    }
+
+    #[test]
+    fn range_parse() {
+        let range = |start, end| Some(Range { start, end });
+        let (current, last) = (Address::Current, Address::Last);
+        assert_eq!(Range::parse("%d"), (Some(Range::all()), "d"));
+        assert_eq!(
+            Range::parse("3"),
+            (range(Address::Line(3), Address::Line(3)), "")
+        );
+        assert_eq!(
+            Range::parse("2,5m0"),
+            (range(Address::Line(2), Address::Line(5)), "m0")
+        );
+        assert_eq!(Range::parse(".,$s/a/b/"), (range(current, last), "s/a/b/"));
+        // a comma not followed by an address is left for the command
+        assert_eq!(
+            Range::parse("1,x"),
+            (range(Address::Line(1), Address::Line(1)), ",x")
+        );
+        assert_eq!(Range::parse("wq"), (None, "wq"));
+        assert_eq!(Range::all().resolve(4, 9), (0, 9));
+        assert_eq!(
+            range(Address::Line(7), current).unwrap().resolve(2, 9),
+            (2, 6)
+        );
+        assert_eq!(
+            range(Address::Line(20), last).unwrap().resolve(0, 9),
+            (9, 9)
+        );
+    }
+}
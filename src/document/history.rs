@@ -0,0 +1,151 @@
+use crate::app::Position;
+
+use super::DocLine;
+
+/// Lines `start..start + added` took the place of `removed`. Reverting it swaps the two back,
+/// turning it into its own inverse.
+#[derive(Debug)]
+struct Splice {
+    start: usize,
+    removed: Vec<DocLine>,
+    added: usize,
+}
+
+/// Lines `start..end` were replaced by `added` lines, as reverting a splice tells.
+pub type Spliced = (usize, usize, usize);
+
+/// One undoable change: every splice recorded between two commits.
+#[derive(Debug)]
+struct Change {
+    splices: Vec<Splice>,
+    cursor: Position,
+}
+
+/// The changes made to a document, of which the first `applied` are in effect.
+#[derive(Debug)]
+pub struct History {
+    changes: Vec<Change>,
+    applied: usize,
+    pending: Vec<Splice>,
+    pending_cursor: Option<Position>,
+    /// How many changes were applied when the document was last saved, if that point still
+    /// exists.
+    saved: Option<usize>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            changes: Vec::new(),
+            applied: 0,
+            pending: Vec::new(),
+            pending_cursor: None,
+            saved: Some(0),
+        }
+    }
+}
+
+impl Splice {
+    fn revert(&mut self, lines: &mut Vec<DocLine>) -> Spliced {
+        let removed = std::mem::take(&mut self.removed);
+        let added = removed.len();
+        let end = self.start + self.added;
+        self.removed = lines.splice(self.start..end, removed).collect();
+        self.added = added;
+        (self.start, end, added)
+    }
+}
+
+impl History {
+    /// Note that lines `start..start + added` replaced `removed`, as part of the change under way.
+    pub fn record(&mut self, start: usize, removed: Vec<DocLine>, added: usize) {
+        self.pending.push(Splice {
+            start,
+            removed,
+            added,
+        });
+    }
+
+    /// Remember `cursor` as where the next change starts, unless one is already under way.
+    pub fn checkpoint(&mut self, cursor: Position) {
+        if self.pending.is_empty() {
+            self.pending_cursor = Some(cursor);
+        }
+    }
+
+    /// Close the change under way, making it one step to undo. Changes undone before it are
+    /// dropped.
+    pub fn commit(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        self.changes.truncate(self.applied);
+        if self.saved.is_some_and(|saved| saved > self.applied) {
+            self.saved = None;
+        }
+        let splices = std::mem::take(&mut self.pending);
+        let cursor = self.pending_cursor.take().unwrap_or(Position {
+            row: splices[0].start as u16,
+            col: 0,
+        });
+        self.changes.push(Change { splices, cursor });
+        self.applied += 1;
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.applied);
+    }
+
+    /// Whether the changes in effect are the ones that were last saved.
+    pub fn at_saved(&self) -> bool {
+        self.pending.is_empty() && self.saved == Some(self.applied)
+    }
+
+    /// Undo up to `count` changes, returning how many were undone, where the last of them was
+    /// made, and the splices reverted to do so, in order.
+    pub fn undo(
+        &mut self,
+        lines: &mut Vec<DocLine>,
+        count: usize,
+    ) -> (usize, Option<Position>, Vec<Spliced>) {
+        self.commit();
+        let mut cursor = None;
+        let mut spliced = Vec::new();
+        let mut undone = 0;
+        while undone < count && self.applied > 0 {
+            self.applied -= 1;
+            let change = &mut self.changes[self.applied];
+            spliced.extend(change.splices.iter_mut().rev().map(|sp| sp.revert(lines)));
+            cursor = Some(change.cursor);
+            undone += 1;
+        }
+        (undone, cursor, spliced)
+    }
+
+    /// Redo up to `count` undone changes, returning how many were redone, where the last of them
+    /// was made, and the splices reverted to do so, in order.
+    pub fn redo(
+        &mut self,
+        lines: &mut Vec<DocLine>,
+        count: usize,
+    ) -> (usize, Option<Position>, Vec<Spliced>) {
+        self.commit();
+        let mut cursor = None;
+        let mut spliced = Vec::new();
+        let mut redone = 0;
+        while redone < count && self.applied < self.changes.len() {
+            let change = &mut self.changes[self.applied];
+            spliced.extend(change.splices.iter_mut().map(|sp| sp.revert(lines)));
+            cursor = Some(change.cursor);
+            self.applied += 1;
+            redone += 1;
+        }
+        (redone, cursor, spliced)
+    }
+
+    /// How many changes are in effect.
+    #[inline]
+    pub fn applied(&self) -> usize {
+        self.applied
+    }
+}
@@ -1,21 +1,26 @@
 use std::{
-    fs::{self, File},
+    collections::BTreeMap,
+    fs::{self, File, Permissions},
     io::{self, BufWriter, Write},
+    ops,
     path::{Path, PathBuf},
 };
 
 use thiserror::Error;
 
-use crate::app::Position;
+use super::history::History;
+use crate::{app::Position, options::Options};
 
 #[derive(Debug, Default)]
 pub struct Document {
     lines: Vec<DocLine>,
     dirty: bool,
     uri: Option<PathBuf>,
+    history: History,
+    marks: BTreeMap<char, Position>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct DocLine {
     pub(self) content: String,
 }
@@ -38,6 +43,10 @@ impl DocLine {
             self.content.remove(at);
         }
     }
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.content
+    }
 }
 
 #[derive(Debug, Error)]
@@ -46,6 +55,8 @@ pub enum DocumentError {
     IoErr(#[from] io::Error),
     #[error("No URI is provided")]
     NoUri,
+    #[error("File is read-only")]
+    ReadOnly,
 }
 
 impl Document {
@@ -60,6 +71,17 @@ impl Document {
             lines,
             dirty: true,
             uri: None,
+            history: History::default(),
+            marks: BTreeMap::new(),
+        }
+    }
+
+    /// A document holding `text`, with no file behind it.
+    #[cfg(test)]
+    pub fn from_text(text: &str) -> Self {
+        Self {
+            lines: text.lines().map(DocLine::from_str).collect(),
+            ..Self::default()
         }
     }
 
@@ -70,67 +92,742 @@ impl Document {
             lines,
             dirty: false,
             uri: Some(PathBuf::from(path.as_ref())),
+            history: History::default(),
+            marks: BTreeMap::new(),
         })
     }
 
     pub fn save(&mut self) -> Result<(), DocumentError> {
-        if self.uri.is_none() {
+        let Some(uri) = &self.uri else {
             return Err(DocumentError::NoUri);
+        };
+        if fs::metadata(uri).is_ok_and(|meta| meta.permissions().readonly()) {
+            return Err(DocumentError::ReadOnly);
         }
+        self.write()
+    }
+
+    /// Save even over a read-only file, making it writable only for as long as the write takes.
+    pub fn force_save(&mut self) -> Result<(), DocumentError> {
+        let Some(uri) = self.uri.clone() else {
+            return Err(DocumentError::NoUri);
+        };
+        let permissions = match fs::metadata(&uri) {
+            Ok(meta) if meta.permissions().readonly() => meta.permissions(),
+            _ => return self.write(),
+        };
 
+        // should this fail, the write below reports why the file cannot be written
+        let unlocked = fs::set_permissions(&uri, writable(permissions.clone())).is_ok();
+        let result = self.write();
+        // the outcome of the write is what matters, so a file left writable goes unreported
+        if unlocked {
+            let _ = fs::set_permissions(&uri, permissions);
+        }
+        result
+    }
+
+    fn write(&mut self) -> Result<(), DocumentError> {
+        self.history.commit();
         let file = File::create(self.uri.as_ref().unwrap())?;
         let mut writer = BufWriter::new(file);
-        for line in self.lines.iter() {
-            writeln!(writer, "{}", line.content)?;
+        // a buffer emptied down to a single blank line is saved as an empty file
+        if !(self.lines.len() == 1 && self.lines[0].content.is_empty()) {
+            for line in self.lines.iter() {
+                writeln!(writer, "{}", line.content)?;
+            }
         }
+        writer.flush()?;
         self.dirty = false;
+        self.history.mark_saved();
         Ok(())
     }
 
-    pub fn insert(&mut self, at: Position, ch: char) {
+    /// Apply `edit` to the lines, recording it for undo as having replaced lines `start..end`,
+    /// which must be all that it touches.
+    fn edit(&mut self, start: usize, end: usize, edit: impl FnOnce(&mut Vec<DocLine>)) {
+        let removed = self.lines[start..end].to_vec();
+        let len = self.lines.len();
+        edit(&mut self.lines);
+        let added = self.lines.len() + removed.len() - len;
+        self.shift_marks(start, start + removed.len(), added);
+        self.history.record(start, removed, added);
         self.dirty = true;
-        if (at.row as usize) < self.line_count() {
-            let ln = self.lines.get_mut(at.row as usize).unwrap();
-            ln.insert(at.col as usize, ch);
+    }
+
+    /// Keep the marks on their lines now that lines `start..end` are replaced by `added` lines.
+    /// Those on lines deleted outright are dropped.
+    fn shift_marks(&mut self, start: usize, end: usize, added: usize) {
+        self.marks.retain(|_, mark| {
+            let row = mark.row as usize;
+            if row >= end {
+                mark.row = (row + added - (end - start)) as u16;
+            } else if row >= start + added {
+                if added == 0 {
+                    return false;
+                }
+                mark.row = (start + added - 1) as u16;
+            }
+            true
+        });
+    }
+
+    /// Set mark `name` at `pos`.
+    pub fn set_mark(&mut self, name: char, pos: Position) {
+        self.marks.insert(name, pos);
+    }
+
+    /// Where mark `name` is, if it is set.
+    pub fn mark(&self, name: char) -> Option<Position> {
+        self.marks.get(&name).copied()
+    }
+
+    /// The marks set, in order of name.
+    pub fn marks(&self) -> impl Iterator<Item = (char, Position)> + '_ {
+        self.marks.iter().map(|(name, pos)| (*name, *pos))
+    }
+
+    /// Remember `cursor` as the place to return to when undoing the next change.
+    pub fn checkpoint(&mut self, cursor: Position) {
+        self.history.checkpoint(cursor);
+    }
+
+    /// Close the change under way, so that it is undone as one step.
+    pub fn commit(&mut self) {
+        self.history.commit();
+    }
+
+    /// Undo up to `count` changes, returning how many were undone and where the cursor belongs.
+    pub fn undo(&mut self, count: usize) -> (usize, Option<Position>) {
+        let (undone, cursor, spliced) = self.history.undo(&mut self.lines, count);
+        for (start, end, added) in spliced {
+            self.shift_marks(start, end, added);
+        }
+        self.dirty = !self.history.at_saved();
+        (undone, cursor)
+    }
+
+    /// Redo up to `count` undone changes, returning how many were redone and where the cursor
+    /// belongs.
+    pub fn redo(&mut self, count: usize) -> (usize, Option<Position>) {
+        let (redone, cursor, spliced) = self.history.redo(&mut self.lines, count);
+        for (start, end, added) in spliced {
+            self.shift_marks(start, end, added);
+        }
+        self.dirty = !self.history.at_saved();
+        (redone, cursor)
+    }
+
+    /// How many changes are in effect, i.e. the number of the latest one.
+    #[inline]
+    pub fn change_number(&self) -> usize {
+        self.history.applied()
+    }
+
+    pub fn insert(&mut self, at: Position, ch: char) {
+        let row = at.row as usize;
+        if row < self.line_count() {
+            self.edit(row, row + 1, |lines| lines[row].insert(at.col as usize, ch));
         } else {
-            let mut ln = DocLine::default();
-            ln.insert(at.col as usize, ch);
-            self.lines.push(ln);
+            let len = self.line_count();
+            self.edit(len, len, |lines| {
+                let mut ln = DocLine::default();
+                ln.insert(at.col as usize, ch);
+                lines.push(ln);
+            });
         }
     }
 
     pub fn delete(&mut self, at: Position) {
-        self.dirty = true;
-        if (at.row as usize) < self.line_count() {
-            let row = self.lines.get_mut(at.row as usize).unwrap();
-            row.delete(at.col as usize);
+        let row = at.row as usize;
+        if (at.col as usize) < self.get_line_len(row) {
+            self.edit(row, row + 1, |lines| lines[row].delete(at.col as usize));
         }
     }
 
+    /// Delete up to `count` characters from `at` to the end of its line, returning them.
+    pub fn delete_chars(&mut self, at: Position, count: usize) -> String {
+        let (row, col) = (at.row as usize, at.col as usize);
+        let Some(rest) = self.get_line(row).and_then(|ln| ln.get(col..)) else {
+            return String::new();
+        };
+        let len = (rest.char_indices().nth(count)).map_or(rest.len(), |(ind, _)| ind);
+        let removed = rest[..len].to_string();
+        if len != 0 {
+            self.replace_at(row, col, len, "");
+        }
+        removed
+    }
+
+    /// Delete up to `count` characters before `at` back to the start of its line, returning them.
+    pub fn delete_chars_before(&mut self, at: Position, count: usize) -> String {
+        let Some(before) =
+            (self.get_line(at.row as usize)).and_then(|ln| ln.get(..at.col as usize))
+        else {
+            return String::new();
+        };
+        let start = (before.char_indices().rev())
+            .nth(count.max(1) - 1)
+            .map_or(0, |(ind, _)| ind);
+        let count = before[start..].chars().count();
+        let start = Position {
+            row: at.row,
+            col: start as u16,
+        };
+        self.delete_chars(start, count)
+    }
+
+    /// Insert `text` at `at`, breaking the line at every `\n`, and return the position just after
+    /// it.
+    pub fn insert_str(&mut self, at: Position, text: &str) -> Position {
+        let (row, col) = (at.row as usize, at.col as usize);
+        if row >= self.lines.len() || text.is_empty() {
+            return at;
+        }
+        let mut parts: Vec<DocLine> = text.split('\n').map(DocLine::from_str).collect();
+        let added = parts.len() - 1;
+        let end_col = match added {
+            0 => col + text.len(),
+            _ => parts[added].content.len(),
+        };
+        self.edit(row, row + 1, |lines| {
+            let tail = lines[row].content.split_off(col);
+            parts[added].content.push_str(&tail);
+            let first = parts.remove(0);
+            lines[row].content.push_str(&first.content);
+            lines.splice(row + 1..row + 1, parts);
+        });
+        Position {
+            row: (row + added) as u16,
+            col: end_col as u16,
+        }
+    }
+
+    /// Cut line `row` off at `col`, returning the removed tail.
+    pub fn truncate_line(&mut self, row: usize, col: usize) -> String {
+        let Some(tail) = self.get_line(row).and_then(|ln| ln.get(col..)) else {
+            return String::new();
+        };
+        let removed = tail.to_string();
+        if !removed.is_empty() {
+            self.edit(row, row + 1, |lines| lines[row].content.truncate(col));
+        }
+        removed
+    }
+
+    /// The text from `start` up to but not including `end`, with the line breaks in between as
+    /// `\n`.
+    pub fn get_range(&self, start: Position, end: Position) -> String {
+        let (start_row, end_row) = (start.row as usize, end.row as usize);
+        if start_row > end_row || end_row >= self.lines.len() {
+            return String::new();
+        }
+        let (start_col, end_col) = (start.col as usize, end.col as usize);
+        if start_row == end_row {
+            let line = self.lines[start_row].as_str();
+            return (line.get(start_col..end_col.min(line.len())))
+                .unwrap_or_default()
+                .to_string();
+        }
+        let first = self.lines[start_row].as_str().get(start_col..);
+        let mut text = first.unwrap_or_default().to_string();
+        for line in &self.lines[start_row + 1..end_row] {
+            text.push('\n');
+            text.push_str(line.as_str());
+        }
+        text.push('\n');
+        let last = self.lines[end_row].as_str();
+        text.push_str(&last[..end_col.min(last.len())]);
+        text
+    }
+
+    /// Delete the text from `start` up to but not including `end`, returning it with the line
+    /// breaks in between as `\n`.
+    pub fn delete_range(&mut self, start: Position, end: Position) -> String {
+        let removed = self.get_range(start, end);
+        if removed.is_empty() {
+            return removed;
+        }
+
+        let (start_row, end_row) = (start.row as usize, end.row as usize);
+        let (start_col, end_col) = (start.col as usize, end.col as usize);
+        self.edit(start_row, end_row + 1, |lines| {
+            let last = lines[end_row].as_str();
+            let tail = last[end_col.min(last.len())..].to_string();
+            lines[start_row].content.truncate(start_col);
+            lines[start_row].content.push_str(&tail);
+            lines.drain(start_row + 1..=end_row);
+        });
+        removed
+    }
+
+    /// The columns `start.col..end.col` of lines `start.row..=end.row`, cut short on lines that
+    /// are.
+    pub fn get_block(&self, start: Position, end: Position) -> Vec<String> {
+        (start.row as usize..=end.row as usize)
+            .filter_map(|row| {
+                let line = self.get_line(row)?;
+                Some(line[block_cols(line, start.col, end.col)].to_string())
+            })
+            .collect()
+    }
+
+    /// Delete the columns `start.col..end.col` of lines `start.row..=end.row`, returning what
+    /// was removed from each.
+    pub fn delete_block(&mut self, start: Position, end: Position) -> Vec<String> {
+        let removed = self.get_block(start, end);
+        if removed.iter().all(String::is_empty) {
+            return removed;
+        }
+
+        let row = start.row as usize;
+        self.edit(row, row + removed.len(), |lines| {
+            for line in &mut lines[row..row + removed.len()] {
+                let cols = block_cols(&line.content, start.col, end.col);
+                line.content.replace_range(cols, "");
+            }
+        });
+        removed
+    }
+
+    /// Insert each of `texts` at column `at.col` of the lines from `at.row` on. Lines too short
+    /// for the column are padded with spaces, and missing ones added, if `pad`; otherwise they
+    /// are left alone.
+    pub fn insert_block(&mut self, at: Position, texts: &[String], pad: bool) {
+        let row = at.row as usize;
+        if row >= self.lines.len() || texts.is_empty() {
+            return;
+        }
+
+        let col = at.col as usize;
+        let end = (row + texts.len()).min(self.lines.len());
+        self.edit(row, end, |lines| {
+            for (ind, text) in texts.iter().enumerate() {
+                if row + ind == lines.len() {
+                    if !pad {
+                        break;
+                    }
+                    lines.push(DocLine::default());
+                }
+                let line = &mut lines[row + ind].content;
+                if line.len() < col {
+                    if !pad {
+                        continue;
+                    }
+                    line.push_str(&" ".repeat(col - line.len()));
+                }
+                let col = block_cols(line, col as u16, col as u16).start;
+                line.insert_str(col, text);
+            }
+        });
+    }
+
+    /// Rewrite the text from `start` up to but not including `end` with `transform`, line by line,
+    /// returning whether that changed anything.
+    pub fn transform_range(
+        &mut self,
+        start: Position,
+        end: Position,
+        transform: impl Fn(&str) -> String,
+    ) -> bool {
+        let start_row = start.row as usize;
+        let end_row = (end.row as usize).min(self.lines.len().saturating_sub(1));
+        if self.lines.is_empty() || start_row > end_row {
+            return false;
+        }
+
+        let rewritten: Vec<_> = (start_row..=end_row)
+            .map(|row| {
+                let line = &self.lines[row].content;
+                let from = if row == start_row { start.col } else { 0 };
+                let to = if row == end.row as usize {
+                    end.col
+                } else {
+                    line.len() as u16
+                };
+                let cols = block_cols(line, from, to);
+                let text = transform(&line[cols.clone()]);
+                (text != line[cols.clone()]).then(|| {
+                    let mut line = line.clone();
+                    line.replace_range(cols, &text);
+                    line
+                })
+            })
+            .collect();
+        if rewritten.iter().all(Option::is_none) {
+            return false;
+        }
+        self.edit(start_row, end_row + 1, |lines| {
+            for (line, text) in lines[start_row..=end_row].iter_mut().zip(rewritten) {
+                if let Some(text) = text {
+                    line.content = text;
+                }
+            }
+        });
+        true
+    }
+
+    /// Insert `line` before line `row`, or at the end if `row` is past the last line.
+    pub fn insert_line(&mut self, row: usize, line: DocLine) {
+        self.insert_lines(row, vec![line]);
+    }
+
+    /// Insert `lines` before line `row`, or at the end if `row` is past the last line.
+    pub fn insert_lines(&mut self, row: usize, lines: Vec<DocLine>) {
+        if lines.is_empty() {
+            return;
+        }
+        let row = row.min(self.lines.len());
+        self.edit(row, row, |doc| {
+            doc.splice(row..row, lines);
+        });
+    }
+
+    /// Replace lines `start..=end` with `lines`, leaving a single empty line behind if none
+    /// remain.
+    pub fn replace_lines(&mut self, start: usize, end: usize, lines: Vec<DocLine>) {
+        let start = start.min(self.lines.len());
+        let end = (end + 1).clamp(start, self.lines.len());
+        self.edit(start, end, |doc| {
+            doc.splice(start..end, lines);
+            if doc.is_empty() {
+                doc.push(DocLine::default());
+            }
+        });
+    }
+
+    /// Remove lines `start..=end` and hand them back, leaving a single empty line behind if none
+    /// remain.
+    pub fn delete_lines(&mut self, start: usize, end: usize) -> Vec<DocLine> {
+        if start >= self.lines.len() {
+            return Vec::new();
+        }
+        let end = end.min(self.lines.len() - 1);
+        let mut removed = Vec::new();
+        self.edit(start, end + 1, |lines| {
+            removed = lines.drain(start..=end).collect();
+            if lines.is_empty() {
+                lines.push(DocLine::default());
+            }
+        });
+        removed
+    }
+
+    /// Remove line `row` and hand it back, leaving a single empty line behind if it was the only
+    /// one.
+    pub fn delete_line(&mut self, row: usize) -> Option<DocLine> {
+        self.delete_lines(row, row).pop()
+    }
+
+    /// Move lines `start..=end` to just below the first `dest` lines, counted before the move,
+    /// returning whether anything moved.
+    pub fn move_lines(&mut self, start: usize, end: usize, dest: usize) -> bool {
+        if dest == start || dest == end + 1 || end >= self.lines.len() {
+            return false;
+        }
+        let first = start.min(dest);
+        let last = (end + 1).max(dest);
+        self.edit(first, last, |lines| {
+            let block: Vec<_> = lines.drain(start..=end).collect();
+            let at = if dest > end { dest - block.len() } else { dest };
+            lines.splice(at..at, block);
+        });
+        true
+    }
+
+    /// Insert copies of lines `start..=end` just below the first `dest` lines.
+    pub fn copy_lines(&mut self, start: usize, end: usize, dest: usize) {
+        let Some(block) = self.lines.get(start..=end) else {
+            return;
+        };
+        let block = block.to_vec();
+        self.insert_lines(dest, block);
+    }
+
+    /// Shift lines `start..=end` right by `levels` times `shiftwidth` columns, or left for
+    /// negative `levels`, rewriting their indentation by `tabstop` and `expandtab`. Empty lines
+    /// stay empty, and lines are shifted left no further than they are indented. Returns how
+    /// many lines changed.
+    pub fn indent_lines(
+        &mut self,
+        start: usize,
+        end: usize,
+        levels: isize,
+        opts: &Options,
+    ) -> usize {
+        let end = end.min(self.lines.len().saturating_sub(1));
+        if self.lines.is_empty() || start > end {
+            return 0;
+        }
+        let tabstop = (opts.tabstop as usize).max(1);
+        // a `shiftwidth` of 0 follows `tabstop`
+        let shiftwidth = match opts.shiftwidth {
+            0 => tabstop,
+            sw => sw as usize,
+        };
+
+        let shifted: Vec<_> = (self.lines[start..=end].iter())
+            .map(|line| {
+                let content = &line.content;
+                if content.is_empty() {
+                    return None;
+                }
+                let rest = content.trim_start_matches([' ', '\t']);
+                let indent = &content[..content.len() - rest.len()];
+                let width = (indent.chars()).fold(0, |col, ch| next_col(col, ch, tabstop));
+                let width = (width as isize + levels * shiftwidth as isize).max(0) as usize;
+                let mut shifted = String::with_capacity(width + rest.len());
+                push_whitespace(&mut shifted, 0, width, tabstop, opts.expandtab);
+                shifted.push_str(rest);
+                (shifted != *content).then_some(shifted)
+            })
+            .collect();
+        let changed = shifted.iter().flatten().count();
+        if changed > 0 {
+            self.edit(start, end + 1, |lines| {
+                for (line, shifted) in lines[start..=end].iter_mut().zip(shifted) {
+                    if let Some(shifted) = shifted {
+                        line.content = shifted;
+                    }
+                }
+            });
+        }
+        changed
+    }
+
+    /// Rewrite the leading whitespace of line `row`, or all of its whitespace if `all`, as spaces
+    /// or as tabs padded with spaces, keeping it as wide as it is with tabs every `tabstop`
+    /// columns. Returns whether the line changed.
+    pub fn retab_line(&mut self, row: usize, tabstop: usize, expandtab: bool, all: bool) -> bool {
+        let Some(line) = self.lines.get(row) else {
+            return false;
+        };
+        let tabstop = tabstop.max(1);
+        let mut retabbed = String::with_capacity(line.content.len());
+        let mut col = 0;
+        let mut run_start = None;
+        let mut leading = true;
+        for ch in line.content.chars() {
+            if (ch == ' ' || ch == '\t') && (leading || all) {
+                run_start.get_or_insert(col);
+                col = next_col(col, ch, tabstop);
+                continue;
+            }
+            if let Some(start) = run_start.take() {
+                push_whitespace(&mut retabbed, start, col, tabstop, expandtab);
+            }
+            leading = false;
+            retabbed.push(ch);
+            col = next_col(col, ch, tabstop);
+        }
+        if let Some(start) = run_start {
+            push_whitespace(&mut retabbed, start, col, tabstop, expandtab);
+        }
+
+        if retabbed == line.content {
+            return false;
+        }
+        self.edit(row, row + 1, |lines| lines[row].content = retabbed);
+        true
+    }
+
+    /// The screen column of byte `col` on line `row`, with tabs every `tabstop` columns.
+    pub fn display_col(&self, row: usize, col: usize, tabstop: usize) -> usize {
+        let line = self.get_line(row).unwrap_or_default();
+        (line.char_indices())
+            .take_while(|(ind, _)| *ind < col)
+            .fold(0, |display, (_, ch)| next_col(display, ch, tabstop.max(1)))
+    }
+
+    /// Line `row` as it is shown, each tab spread out with spaces to the next tab stop.
+    pub fn display_line(&self, row: usize, tabstop: usize) -> Option<String> {
+        let line = self.get_line(row)?;
+        let mut shown = String::with_capacity(line.len());
+        let mut col = 0;
+        for ch in line.chars() {
+            let next = next_col(col, ch, tabstop.max(1));
+            match ch {
+                '\t' => shown.push_str(&" ".repeat(next - col)),
+                ch => shown.push(ch),
+            }
+            col = next;
+        }
+        Some(shown)
+    }
+
+    /// The byte on line `row` shown at screen column `display`, or the last one before it.
+    pub fn col_at_display(&self, row: usize, display: usize, tabstop: usize) -> usize {
+        let line = self.get_line(row).unwrap_or_default();
+        let mut col = 0;
+        for (ind, ch) in line.char_indices() {
+            if col == display {
+                return ind;
+            }
+            col = next_col(col, ch, tabstop.max(1));
+            if col > display {
+                return ind;
+            }
+        }
+        line.len()
+    }
+
     pub fn merge_line_into_up(&mut self, row: usize) {
-        self.dirty = true;
-        let line = self.lines.remove(row);
-        self.lines
-            .get_mut(row.saturating_sub(1))
-            .unwrap()
-            .content
-            .push_str(&line.content);
+        if row == 0 || row >= self.lines.len() {
+            return;
+        }
+        self.edit(row - 1, row + 1, |lines| {
+            let line = lines.remove(row);
+            lines[row - 1].content.push_str(&line.content);
+        });
+    }
+
+    /// Join `count` lines from `row` into one, with the leading whitespace of each line joined
+    /// replaced by a space, returning the column where the last of them was joined. Nothing
+    /// happens when `row` is the last line.
+    pub fn join_lines(&mut self, row: usize, count: usize) -> Option<usize> {
+        let end = (row + count.max(2) - 1).min(self.lines.len().saturating_sub(1));
+        if row >= end {
+            return None;
+        }
+        let mut col = 0;
+        self.edit(row, end + 1, |lines| {
+            for line in lines.drain(row + 1..=end).collect::<Vec<_>>() {
+                let joined = &mut lines[row].content;
+                let next = line.content.trim_start();
+                col = joined.len();
+                if !(next.is_empty() || joined.is_empty() || joined.ends_with(' ')) {
+                    joined.push(' ');
+                }
+                joined.push_str(next);
+            }
+        });
+        Some(col)
     }
 
     pub fn split_to_two_line(&mut self, at: Position) {
-        self.dirty = true;
-        let line = self.lines.get_mut(at.row as usize).unwrap();
-        let new_line = line.content.split_off(at.col as usize);
-        self.lines.insert(
-            at.row.saturating_add(1) as usize,
-            DocLine::from_str(new_line.as_str()),
-        );
+        let row = at.row as usize;
+        self.edit(row, row + 1, |lines| {
+            let new_line = lines[row].content.split_off(at.col as usize);
+            lines.insert(row + 1, DocLine::from_str(new_line.as_str()));
+        });
+    }
+
+    /// Replace the first (or every, if `global`) occurrence of `pattern` on line `row`, returning
+    /// the number of replacements made.
+    pub fn replace_in_line(
+        &mut self,
+        row: usize,
+        pattern: &str,
+        replacement: &str,
+        global: bool,
+    ) -> usize {
+        let Some(line) = self.lines.get(row) else {
+            return 0;
+        };
+        let count = if global {
+            line.content.matches(pattern).count()
+        } else {
+            usize::from(line.content.contains(pattern))
+        };
+        if count > 0 {
+            let content = if global {
+                line.content.replace(pattern, replacement)
+            } else {
+                line.content.replacen(pattern, replacement, 1)
+            };
+            self.edit(row, row + 1, |lines| lines[row].content = content);
+        }
+        count
+    }
+
+    /// Apply [`Self::replace_in_line`] to every line in `start..=end`, returning the number of
+    /// replacements and of lines changed.
+    pub fn replace_in_range(
+        &mut self,
+        start: usize,
+        end: usize,
+        pattern: &str,
+        replacement: &str,
+        global: bool,
+    ) -> (usize, usize) {
+        let mut subs = 0;
+        let mut lines = 0;
+        for row in start..=end {
+            let count = self.replace_in_line(row, pattern, replacement, global);
+            if count > 0 {
+                subs += count;
+                lines += 1;
+            }
+        }
+        (subs, lines)
+    }
+
+    /// Find `pattern` on line `row` at or after byte `from`.
+    pub fn find_in_line(&self, row: usize, pattern: &str, from: usize) -> Option<usize> {
+        let line = self.lines.get(row)?;
+        let found = line.content.get(from..)?.find(pattern)?;
+        Some(from + found)
+    }
+
+    /// Where `pattern` next occurs after `from`, or before it going `backward`, carrying on from
+    /// the other end of the document when it runs out. Also tells whether it had to.
+    pub fn find(&self, pattern: &str, from: Position, backward: bool) -> Option<(Position, bool)> {
+        if pattern.is_empty() || self.lines.is_empty() {
+            return None;
+        }
+        let len = self.lines.len();
+        let (row, col) = ((from.row as usize).min(len - 1), from.col as usize);
+        // every line once, then the start line again for what lies on the other side of `from`
+        for step in 0..=len {
+            let (at, wrapped) = match backward {
+                false => ((row + step) % len, row + step >= len),
+                true => ((row + len - step % len) % len, step > row),
+            };
+            let line = self.lines[at].as_str();
+            let mut starts = (0..=line.len())
+                .filter(|start| line.is_char_boundary(*start))
+                .filter(|start| line[*start..].starts_with(pattern));
+            // a match right at `from` is skipped like vim does, so that searching again moves
+            // on from the one found last, unless there is none other to wrap round to
+            let found = match (backward, step) {
+                (false, 0) => starts.find(|start| *start > col),
+                (false, step) if step == len => starts.find(|start| *start <= col),
+                (false, _) => starts.next(),
+                (true, 0) => starts.rfind(|start| *start < col),
+                (true, step) if step == len => starts.rfind(|start| *start >= col),
+                (true, _) => starts.next_back(),
+            };
+            if let Some(start) = found {
+                let pos = Position {
+                    row: at as u16,
+                    col: start as u16,
+                };
+                return Some((pos, wrapped));
+            }
+        }
+        None
+    }
+
+    /// Replace the `len` bytes at `col` on line `row` with `replacement`.
+    pub fn replace_at(&mut self, row: usize, col: usize, len: usize, replacement: &str) {
+        if row < self.lines.len() {
+            self.edit(row, row + 1, |lines| {
+                lines[row]
+                    .content
+                    .replace_range(col..col + len, replacement)
+            });
+        }
     }
 
     pub fn set_uri(&mut self, uri: impl AsRef<Path>) {
         self.uri = Some(PathBuf::from(uri.as_ref()));
     }
 
+    #[inline]
+    pub fn uri(&self) -> Option<&Path> {
+        self.uri.as_deref()
+    }
+
     #[inline]
     pub fn dirty(&self) -> bool {
         self.dirty
@@ -151,3 +848,158 @@ impl Document {
         self.lines.len()
     }
 }
+
+/// `permissions` with write access granted to the owner.
+fn writable(mut permissions: Permissions) -> Permissions {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.set_mode(permissions.mode() | 0o200);
+    }
+    #[cfg(not(unix))]
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    permissions
+}
+
+/// The bytes of `line` in columns `start..end`, cut short at its end and widened to whole
+/// characters.
+fn block_cols(line: &str, start: u16, end: u16) -> ops::Range<usize> {
+    let mut start = (start as usize).min(line.len());
+    while !line.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (end as usize).clamp(start, line.len());
+    while !line.is_char_boundary(end) {
+        end += 1;
+    }
+    start..end
+}
+
+/// The screen column after `ch` when it is shown at column `col`.
+fn next_col(col: usize, ch: char, tabstop: usize) -> usize {
+    if ch == '\t' {
+        (col / tabstop + 1) * tabstop
+    } else {
+        col + 1
+    }
+}
+
+/// Fill screen columns `start..end` with spaces, or with tabs as far as they go if not
+/// `expandtab`.
+fn push_whitespace(out: &mut String, start: usize, end: usize, tabstop: usize, expandtab: bool) {
+    let mut col = start;
+    if !expandtab {
+        while (col / tabstop + 1) * tabstop <= end {
+            out.push('\t');
+            col = (col / tabstop + 1) * tabstop;
+        }
+    }
+    out.push_str(&" ".repeat(end - col));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(row: u16, col: u16) -> Position {
+        Position { row, col }
+    }
+
+    #[test]
+    fn find_forward() {
+        let doc = Document::from_text("foo bar foo\nbaz foo");
+        assert_eq!(doc.find("foo", pos(0, 0), false), Some((pos(0, 8), false)));
+        assert_eq!(doc.find("foo", pos(0, 8), false), Some((pos(1, 4), false)));
+        assert_eq!(doc.find("bar", pos(0, 2), false), Some((pos(0, 4), false)));
+    }
+
+    #[test]
+    fn find_wraps_past_the_end() {
+        let doc = Document::from_text("foo\nbar\nbaz");
+        assert_eq!(doc.find("foo", pos(2, 0), false), Some((pos(0, 0), true)));
+        assert_eq!(doc.find("baz", pos(0, 0), true), Some((pos(2, 0), true)));
+        // the only match, under the cursor, is found again once round
+        assert_eq!(doc.find("bar", pos(1, 0), false), Some((pos(1, 0), true)));
+    }
+
+    #[test]
+    fn find_backward_skips_the_match_at_the_cursor() {
+        let doc = Document::from_text("foo foo\nfoo");
+        assert_eq!(doc.find("foo", pos(0, 4), true), Some((pos(0, 0), false)));
+        assert_eq!(doc.find("foo", pos(0, 0), true), Some((pos(1, 0), true)));
+        assert_eq!(doc.find("oo", pos(0, 5), true), Some((pos(0, 1), false)));
+    }
+
+    #[test]
+    fn find_multibyte() {
+        let doc = Document::from_text("héllo wörld\nwörld");
+        assert_eq!(
+            doc.find("wörld", pos(0, 0), false),
+            Some((pos(0, 7), false))
+        );
+        assert_eq!(
+            doc.find("wörld", pos(0, 7), false),
+            Some((pos(1, 0), false))
+        );
+        assert_eq!(doc.find("ö", pos(1, 0), true), Some((pos(0, 8), false)));
+        assert_eq!(doc.find("llo", pos(0, 0), false), Some((pos(0, 3), false)));
+    }
+
+    #[test]
+    fn find_nothing() {
+        let doc = Document::from_text("foo\nbar");
+        assert_eq!(doc.find("qux", pos(0, 0), false), None);
+        assert_eq!(doc.find("qux", pos(1, 2), true), None);
+        assert_eq!(doc.find("", pos(0, 0), false), None);
+    }
+
+    #[test]
+    fn undo_and_redo_move_marks_with_their_lines() {
+        let mut doc = Document::from_text("a\nb\nc\nd");
+        doc.set_mark('a', pos(3, 0));
+        doc.delete_lines(0, 1);
+        doc.commit();
+        assert_eq!(doc.mark('a'), Some(pos(1, 0)));
+        doc.undo(1);
+        assert_eq!(doc.mark('a'), Some(pos(3, 0)));
+        doc.redo(1);
+        assert_eq!(doc.mark('a'), Some(pos(1, 0)));
+    }
+
+    #[test]
+    fn indent_lines_of_an_empty_document() {
+        let mut doc = Document::from_text("");
+        assert_eq!(doc.indent_lines(0, 0, 1, &Options::default()), 0);
+        assert_eq!(doc.indent_lines(0, 0, -1, &Options::default()), 0);
+        assert_eq!(doc.line_count(), 0);
+    }
+
+    #[test]
+    fn transform_range_of_an_empty_document() {
+        let mut doc = Document::from_text("");
+        let upper = |text: &str| text.to_uppercase();
+        assert!(!doc.transform_range(pos(0, 0), pos(0, 0), upper));
+        assert_eq!(doc.line_count(), 0);
+    }
+
+    #[test]
+    fn force_save_over_a_read_only_file() {
+        let path = std::env::temp_dir().join(format!("vix-force-save-{}", std::process::id()));
+        fs::write(&path, "old\n").unwrap();
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions).unwrap();
+
+        let mut doc = Document::from_text("new");
+        doc.set_uri(&path);
+        let result = doc.force_save();
+        let content = fs::read_to_string(&path).unwrap();
+        let readonly = fs::metadata(&path).unwrap().permissions().readonly();
+        fs::remove_file(&path).unwrap();
+
+        assert!(result.is_ok() && !doc.dirty());
+        assert_eq!(content, "new\n");
+        assert!(readonly);
+    }
+}
@@ -1,4 +1,6 @@
+mod history;
 mod line_list;
 
+pub use line_list::DocLine;
 pub use line_list::Document;
 pub use line_list::DocumentError;
@@ -0,0 +1,95 @@
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+
+/// How the main area is shared out between windows: nested rows and columns whose leaves are
+/// indices into the window list.
+#[derive(Debug)]
+pub enum Split {
+    Window(usize),
+    Group(Direction, Vec<Split>),
+}
+
+impl Default for Split {
+    fn default() -> Self {
+        Split::Window(0)
+    }
+}
+
+impl Split {
+    /// Split window `ind` along `dir`, placing window `new` before it (above or to the left).
+    pub fn split(&mut self, ind: usize, new: usize, dir: Direction) {
+        match self {
+            Split::Window(win) if *win == ind => {
+                *self = Split::Group(dir, vec![Split::Window(new), Split::Window(ind)]);
+            }
+            Split::Window(_) => {}
+            Split::Group(group_dir, children) => {
+                let pos = (children.iter())
+                    .position(|child| matches!(child, Split::Window(win) if *win == ind));
+                match pos {
+                    Some(pos) if *group_dir == dir => children.insert(pos, Split::Window(new)),
+                    _ => children
+                        .iter_mut()
+                        .for_each(|child| child.split(ind, new, dir)),
+                }
+            }
+        }
+    }
+
+    /// Take window `ind` out, renumbering the windows after it and dissolving groups left with a
+    /// single member.
+    pub fn remove(&mut self, ind: usize) {
+        match self {
+            Split::Window(win) => {
+                if *win > ind {
+                    *win -= 1;
+                }
+            }
+            Split::Group(_, children) => {
+                children.retain(|child| !matches!(child, Split::Window(win) if *win == ind));
+                children.iter_mut().for_each(|child| child.remove(ind));
+                if children.len() == 1 {
+                    *self = children.remove(0);
+                }
+            }
+        }
+    }
+
+    /// The windows from top-left to bottom-right.
+    pub fn order(&self) -> Vec<usize> {
+        match self {
+            Split::Window(ind) => vec![*ind],
+            Split::Group(_, children) => children.iter().flat_map(Split::order).collect(),
+        }
+    }
+
+    /// Lay the windows out over `area`, collecting the area of each window along with the
+    /// separator columns between side-by-side windows.
+    pub fn layout(&self, area: Rect, windows: &mut Vec<(usize, Rect)>, separators: &mut Vec<Rect>) {
+        let (dir, children) = match self {
+            Split::Window(ind) => {
+                windows.push((*ind, area));
+                return;
+            }
+            Split::Group(dir, children) => (dir, children),
+        };
+
+        let count = children.len() as u32;
+        let separated = *dir == Direction::Horizontal;
+        let mut constraints = Vec::new();
+        for ind in 0..children.len() {
+            if separated && ind > 0 {
+                constraints.push(Constraint::Length(1));
+            }
+            constraints.push(Constraint::Ratio(1, count));
+        }
+        let areas = Layout::new(*dir, constraints).split(area);
+
+        let step = if separated { 2 } else { 1 };
+        for (ind, child) in children.iter().enumerate() {
+            if separated && ind > 0 {
+                separators.push(areas[ind * step - 1]);
+            }
+            child.layout(areas[ind * step], windows, separators);
+        }
+    }
+}
@@ -3,7 +3,15 @@ use std::{env, error::Error};
 use app::App;
 
 mod app;
+mod clipboard;
+mod command;
 mod document;
+mod layout;
+mod motion;
+mod options;
+mod register;
+mod shell;
+mod syntax;
 mod tui;
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -0,0 +1,805 @@
+use crate::{app::Position, document::Document};
+
+/// A Normal mode motion, resolved to the position it leads to without moving the cursor, so that
+/// operators can act on the text it covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    /// `w`, the start of the next word
+    WordStart,
+    /// `e`, the end of the current or next word
+    WordEnd,
+    /// `b`, the start of the current or previous word
+    WordBack,
+    /// `0`, the first column
+    LineStart,
+    /// `^`, the first non-blank character of the line
+    FirstNonBlank,
+    /// `$`, the last character of the line
+    LineEnd,
+    /// `NG` and `Ngg`, the first non-blank of line `N`, or of the last line if there is no `N`
+    ToLine(Option<usize>),
+    /// `j` and `k`, the same column on a line below or above
+    Lines { down: bool },
+    /// `+`, `Enter` and `-`, the first non-blank of a line below or above
+    NonBlankLines { down: bool },
+    /// `f`, `F`, `t` and `T`, a character on the line
+    Find(Find),
+    /// `` `x `` and `'x`, the position of a mark, or the first non-blank of its line for `'x`
+    Mark { pos: Position, linewise: bool },
+}
+
+/// A search for a character on the cursor line, forwards or backwards, landing on it or stopping
+/// just short of it with `till`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Find {
+    pub ch: char,
+    pub backward: bool,
+    pub till: bool,
+}
+
+/// A stretch of text around the cursor that an operator or a Visual selection takes in whole.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextObject {
+    /// `iw` and `aw`, the word under the cursor, along with the blanks after it, or else before
+    /// it, for `aw`
+    Word { around: bool },
+    /// `i"`, `a"` and the like, the text between the quotes around the cursor on its line, along
+    /// with the quotes and the blanks after them, or else before them, for `a"`
+    Quote { quote: char, around: bool },
+    /// `i(`, `a(`, `i{`, `a{` and the like, the text between the brackets around the cursor, on
+    /// any lines, along with the brackets for `a(`
+    Bracket {
+        open: char,
+        close: char,
+        around: bool,
+    },
+}
+
+/// The text an operator acts on: the characters from `start` up to but not including `end`, the
+/// lines from `start`'s to `end`'s as a whole, or the columns between theirs on each of those
+/// lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextRange {
+    pub start: Position,
+    pub end: Position,
+    pub kind: RangeKind,
+}
+
+/// Whether a [`TextRange`] takes in characters or whole lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeKind {
+    Charwise,
+    Linewise,
+    Blockwise,
+}
+
+/// What a character is as far as word motions are concerned: a word is a run of `Word` or of
+/// `Punct` characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharClass {
+    Blank,
+    Word,
+    Punct,
+}
+
+impl CharClass {
+    pub fn of(ch: char) -> Self {
+        if ch.is_whitespace() {
+            Self::Blank
+        } else if ch.is_alphanumeric() || ch == '_' {
+            Self::Word
+        } else {
+            Self::Punct
+        }
+    }
+}
+
+impl Motion {
+    /// Whether an operator over this motion takes in the character at its target too.
+    pub fn inclusive(self) -> bool {
+        match self {
+            Self::WordEnd | Self::LineEnd => true,
+            Self::Find(find) => !find.backward,
+            _ => false,
+        }
+    }
+
+    /// Whether an operator over this motion takes in whole lines.
+    pub fn linewise(self) -> bool {
+        matches!(
+            self,
+            Self::ToLine(_)
+                | Self::Lines { .. }
+                | Self::NonBlankLines { .. }
+                | Self::Mark { linewise: true, .. }
+        )
+    }
+
+    /// Whether the motion jumps away, leaving the `` ` `` mark where it started.
+    pub fn is_jump(self) -> bool {
+        matches!(self, Self::ToLine(_) | Self::Mark { .. })
+    }
+
+    /// The text `count` repetitions of the motion from `from` go over, or `None` if the motion
+    /// fails.
+    pub fn range(self, doc: &Document, from: Position, count: usize) -> Option<TextRange> {
+        let mut to = self.target(doc, from, count)?;
+        if self.linewise() {
+            let (first, last) = (from.row.min(to.row), from.row.max(to.row));
+            return Some(TextRange::lines(first as usize, last as usize));
+        }
+        // `dw` on the last word of a line stops at its end instead of taking the line break
+        if self == Self::WordStart && to.row > from.row {
+            let row = to.row as usize - 1;
+            to = Position {
+                row: row as u16,
+                col: doc.get_line_len(row) as u16,
+            };
+        }
+
+        let (start, mut end) = if (to.row, to.col) < (from.row, from.col) {
+            (to, from)
+        } else {
+            (from, to)
+        };
+        if self.inclusive() {
+            let line = doc.get_line(end.row as usize).unwrap_or_default();
+            let ch = line
+                .get(end.col as usize..)
+                .and_then(|rest| rest.chars().next());
+            end.col += ch.map_or(0, char::len_utf8) as u16;
+        }
+        Some(TextRange::chars(start, end))
+    }
+
+    /// Where `count` repetitions of the motion from `from` lead to, or `None` if the motion fails.
+    pub fn target(self, doc: &Document, from: Position, count: usize) -> Option<Position> {
+        let row = from.row as usize;
+        let to = match self {
+            Self::LineStart => Position {
+                row: from.row,
+                col: 0,
+            },
+            Self::FirstNonBlank => Position {
+                row: from.row,
+                col: first_non_blank(doc, row) as u16,
+            },
+            Self::ToLine(line) => {
+                let last = doc.line_count().saturating_sub(1);
+                let row = line.map_or(last, |line| line.saturating_sub(1).min(last));
+                Position {
+                    row: row as u16,
+                    col: first_non_blank(doc, row) as u16,
+                }
+            }
+            Self::Lines { down } | Self::NonBlankLines { down } => {
+                // going past the first or last line stops there, but not moving at all fails
+                let last = doc.line_count().saturating_sub(1);
+                let row = match down {
+                    true if row < last => (row + count.max(1)).min(last),
+                    false if row > 0 => row.saturating_sub(count.max(1)),
+                    _ => return None,
+                };
+                let col = match self {
+                    Self::Lines { .. } => from.col,
+                    _ => first_non_blank(doc, row) as u16,
+                };
+                Position {
+                    row: row as u16,
+                    col,
+                }
+            }
+            Self::LineEnd => {
+                // `N$` goes down N - 1 lines first
+                let row = (row + count.max(1) - 1).min(doc.line_count().saturating_sub(1));
+                let col = last_char_col(doc.get_line(row).unwrap_or_default()).unwrap_or(0);
+                Position {
+                    row: row as u16,
+                    col: col as u16,
+                }
+            }
+            Self::WordStart => (0..count.max(1)).fold(from, |pos, _| word_start(doc, pos)),
+            Self::WordEnd => (0..count.max(1)).fold(from, |pos, _| word_end(doc, pos)),
+            Self::WordBack => (0..count.max(1)).fold(from, |pos, _| word_back(doc, pos)),
+            Self::Find(find) => return find.target(doc, from, count),
+            Self::Mark { pos, linewise } => {
+                // lines deleted since may have left the mark past the end
+                let row = (pos.row as usize).min(doc.line_count().saturating_sub(1));
+                let line = doc.get_line(row).unwrap_or_default();
+                let mut col = match linewise {
+                    true => first_non_blank(doc, row),
+                    false => (pos.col as usize).min(line.len()),
+                };
+                while !line.is_char_boundary(col) {
+                    col -= 1;
+                }
+                Position {
+                    row: row as u16,
+                    col: col as u16,
+                }
+            }
+        };
+        Some(to)
+    }
+}
+
+impl TextRange {
+    /// The characters from `start` up to but not including `end`.
+    pub fn chars(start: Position, end: Position) -> Self {
+        Self {
+            start,
+            end,
+            kind: RangeKind::Charwise,
+        }
+    }
+
+    /// Lines `first..=last` as a whole.
+    pub fn lines(first: usize, last: usize) -> Self {
+        Self {
+            start: Position {
+                row: first as u16,
+                col: 0,
+            },
+            end: Position {
+                row: last as u16,
+                col: 0,
+            },
+            kind: RangeKind::Linewise,
+        }
+    }
+
+    /// The columns from `start`'s up to but not including `end`'s, on each of the lines from
+    /// `start`'s to `end`'s.
+    pub fn block(start: Position, end: Position) -> Self {
+        Self {
+            start,
+            end,
+            kind: RangeKind::Blockwise,
+        }
+    }
+
+    /// The first and last of the lines the range is on.
+    pub fn rows(self) -> (usize, usize) {
+        (self.start.row as usize, self.end.row as usize)
+    }
+}
+
+impl TextObject {
+    /// The text `count` of the object cover at `at`, or `None` if there is none there.
+    pub fn range(self, doc: &Document, at: Position, count: usize) -> Option<TextRange> {
+        if let Some((first, last)) = self.lines(doc, at, count) {
+            return Some(TextRange::lines(first, last));
+        }
+        let (start, end) = self.chars(doc, at, count)?;
+        Some(TextRange::chars(start, end))
+    }
+
+    /// The characters `count` of the object cover at `at`, from its start up to but not including
+    /// its end.
+    fn chars(self, doc: &Document, at: Position, count: usize) -> Option<(Position, Position)> {
+        let line = doc.get_line(at.row as usize)?;
+        let (start, end) = match self {
+            Self::Word { around } => word_object(line, at.col, around, count)?,
+            Self::Quote { quote, around } => quote_object(line, at.col, quote, around)?,
+            Self::Bracket {
+                open,
+                close,
+                around,
+            } => {
+                let (mut start, mut end) = enclosing_brackets(doc, at, open, close, count)?;
+                if around {
+                    end = next_pos(doc, end)?;
+                } else {
+                    start = next_pos(doc, start)?;
+                }
+                return Some((start, end));
+            }
+        };
+        let pos = |col: usize| Position {
+            row: at.row,
+            col: col as u16,
+        };
+        Some((pos(start), pos(end)))
+    }
+
+    /// The lines the object takes in whole instead, if it does: those between a bracket ending
+    /// its line and the one closing it starting its own for `i{` and the like.
+    fn lines(self, doc: &Document, at: Position, count: usize) -> Option<(usize, usize)> {
+        let Self::Bracket {
+            open,
+            close,
+            around: false,
+        } = self
+        else {
+            return None;
+        };
+        let (start, end) = enclosing_brackets(doc, at, open, close, count)?;
+        let (first, last) = (start.row as usize + 1, (end.row as usize).checked_sub(1)?);
+        let before_close = doc.get_line(end.row as usize)?.get(..end.col as usize)?;
+        let ends_line =
+            start.col as usize + open.len_utf8() == doc.get_line_len(start.row as usize);
+        (ends_line && before_close.trim().is_empty() && first <= last).then_some((first, last))
+    }
+}
+
+impl Find {
+    /// The search started by `cmd`, one of `f`, `F`, `t` and `T`, for `ch`.
+    pub fn new(cmd: char, ch: char) -> Self {
+        Self {
+            ch,
+            backward: cmd.is_ascii_uppercase(),
+            till: cmd.eq_ignore_ascii_case(&'t'),
+        }
+    }
+
+    /// Whether the character searched for is right next to `from`, where a `till` search stays.
+    pub fn is_adjacent(self, doc: &Document, from: Position) -> bool {
+        let line = doc.get_line(from.row as usize).unwrap_or_default();
+        let col = from.col as usize;
+        let next = match self.backward {
+            true => line
+                .get(..col)
+                .and_then(|before| before.chars().next_back()),
+            false => line.get(col..).and_then(|after| after.chars().nth(1)),
+        };
+        next == Some(self.ch)
+    }
+
+    /// The column of the `count`th `ch` from `from` on its line, or just short of it.
+    fn target(self, doc: &Document, from: Position, count: usize) -> Option<Position> {
+        let line = doc.get_line(from.row as usize)?;
+        let col = from.col as usize;
+        let count = count.max(1);
+        let col = if self.backward {
+            let (found, ch) = (line.get(..col)?.char_indices().rev())
+                .filter(|(_, ch)| *ch == self.ch)
+                .nth(count - 1)?;
+            if self.till {
+                found + ch.len_utf8()
+            } else {
+                found
+            }
+        } else {
+            let under = line.get(col..)?.chars().next()?.len_utf8();
+            let found = col
+                + under
+                + (line[col + under..].match_indices(self.ch))
+                    .nth(count - 1)?
+                    .0;
+            if self.till {
+                line[..found]
+                    .char_indices()
+                    .next_back()
+                    .map_or(0, |(col, _)| col)
+            } else {
+                found
+            }
+        };
+        Some(Position {
+            row: from.row,
+            col: col as u16,
+        })
+    }
+}
+
+/// The columns of `count` words of `line` from `col` on, a run of blanks counting as a word
+/// when on one. `around` takes in the blanks after each word too, or the ones before the first
+/// if there are none after, or the word after each run of blanks when on blanks.
+fn word_object(line: &str, col: u16, around: bool, count: usize) -> Option<(usize, usize)> {
+    let class = line
+        .get(col as usize..)?
+        .chars()
+        .next()
+        .map(CharClass::of)?;
+    let blank_at = |col: usize| line[col..].starts_with(char::is_whitespace);
+    let mut start = run_start(line, col as usize);
+    let mut end = start;
+    let mut trailing = false;
+    for _ in 0..count.max(1) {
+        end = run_end(line, end);
+        trailing = false;
+        if !around || end == line.len() {
+            continue;
+        }
+        trailing = blank_at(end);
+        if class == CharClass::Blank || trailing {
+            end = run_end(line, end);
+        }
+    }
+    if around && class != CharClass::Blank && !trailing && start > 0 && blank_at(start - 1) {
+        start = run_start(line, start - 1);
+    }
+    Some((start, end))
+}
+
+/// The columns of the text between the `quote`s around `col`, or the next ones on the line if
+/// there are none around it, telling quotes apart from others escaped with a backslash.
+/// `around` takes in the quotes too, and the blanks after them, or else before them.
+fn quote_object(line: &str, col: u16, quote: char, around: bool) -> Option<(usize, usize)> {
+    let col = col as usize;
+    let mut quotes = Vec::new();
+    let mut escaped = false;
+    for (at, ch) in line.char_indices() {
+        if ch == quote && !escaped {
+            quotes.push(at);
+        }
+        escaped = ch == '\\' && !escaped;
+    }
+    // quotes pair up from the start of the line
+    let (open, close) = (quotes.chunks_exact(2))
+        .map(|pair| (pair[0], pair[1]))
+        .find(|(_, close)| col <= *close)?;
+    if !around {
+        return Some((open + quote.len_utf8(), close));
+    }
+    let (mut start, mut end) = (open, close + quote.len_utf8());
+    let blanks_after = line[end..].len() - line[end..].trim_start().len();
+    if blanks_after > 0 {
+        end += blanks_after;
+    } else {
+        start = line[..start].trim_end().len();
+    }
+    Some((start, end))
+}
+
+/// The `open` bracket around `at`, `count` levels out, and the `close` one matching it. A bracket
+/// under the cursor counts as around it.
+fn enclosing_brackets(
+    doc: &Document,
+    at: Position,
+    open: char,
+    close: char,
+    count: usize,
+) -> Option<(Position, Position)> {
+    let mut start = match char_at(doc, at) {
+        Some(ch) if ch == open => at,
+        _ => unmatched(doc, at, open, close, true)?,
+    };
+    for _ in 1..count.max(1) {
+        start = unmatched(doc, start, open, close, true)?;
+    }
+    let end = unmatched(doc, start, open, close, false)?;
+    Some((start, end))
+}
+
+/// The nearest bracket after `from` that closes one opened before it, or before `from` that
+/// opens one closed after it going `backward`, skipping over the pairs in between.
+fn unmatched(
+    doc: &Document,
+    from: Position,
+    open: char,
+    close: char,
+    backward: bool,
+) -> Option<Position> {
+    let (wanted, nested) = match backward {
+        true => (open, close),
+        false => (close, open),
+    };
+    let mut depth = 0;
+    let mut pos = from;
+    loop {
+        pos = match backward {
+            true => prev_pos(doc, pos)?,
+            false => next_pos(doc, pos)?,
+        };
+        match char_at(doc, pos) {
+            Some(ch) if ch == wanted && depth == 0 => return Some(pos),
+            Some(ch) if ch == wanted => depth -= 1,
+            Some(ch) if ch == nested => depth += 1,
+            _ => {}
+        }
+    }
+}
+
+/// The column where the run of characters of the same class as the one at `col` starts.
+fn run_start(line: &str, col: usize) -> usize {
+    let class = line[col..].chars().next().map(CharClass::of);
+    (line[..col].char_indices().rev())
+        .take_while(|(_, ch)| Some(CharClass::of(*ch)) == class)
+        .last()
+        .map_or(col, |(start, _)| start)
+}
+
+/// The column just past the run of characters of the same class as the one at `col`.
+fn run_end(line: &str, col: usize) -> usize {
+    let class = line[col..].chars().next().map(CharClass::of);
+    (line[col..].char_indices())
+        .find(|(_, ch)| Some(CharClass::of(*ch)) != class)
+        .map_or(line.len(), |(len, _)| col + len)
+}
+
+/// Where `b` lands in `text` from its end: the start of the word before it, over any blanks.
+pub fn line_word_back(text: &str) -> usize {
+    let text = text.trim_end();
+    last_char_col(text).map_or(0, |col| run_start(text, col))
+}
+
+/// The character at `pos`, `None` past the end of its line.
+fn char_at(doc: &Document, pos: Position) -> Option<char> {
+    let line = doc.get_line(pos.row as usize)?;
+    line.get(pos.col as usize..)?.chars().next()
+}
+
+/// The column of the first non-blank character on line `row`, or 0 if there is none.
+pub fn first_non_blank(doc: &Document, row: usize) -> usize {
+    let line = doc.get_line(row).unwrap_or_default();
+    line.find(|ch: char| !ch.is_whitespace()).unwrap_or(0)
+}
+
+/// The column of the last character of `line`.
+fn last_char_col(line: &str) -> Option<usize> {
+    line.char_indices().next_back().map(|(col, _)| col)
+}
+
+/// The position after `pos`, moving onto the start of the next line from the end of one.
+fn next_pos(doc: &Document, pos: Position) -> Option<Position> {
+    match char_at(doc, pos) {
+        Some(ch) => Some(Position {
+            row: pos.row,
+            col: pos.col + ch.len_utf8() as u16,
+        }),
+        None if (pos.row as usize) + 1 < doc.line_count() => Some(Position {
+            row: pos.row + 1,
+            col: 0,
+        }),
+        None => None,
+    }
+}
+
+/// The position before `pos`, moving onto the end of the previous line from the start of one.
+fn prev_pos(doc: &Document, pos: Position) -> Option<Position> {
+    let line = doc.get_line(pos.row as usize)?;
+    if let Some((col, _)) = line.get(..pos.col as usize)?.char_indices().next_back() {
+        return Some(Position {
+            row: pos.row,
+            col: col as u16,
+        });
+    }
+    let row = (pos.row as usize).checked_sub(1)?;
+    let col = doc.get_line(row).map_or(0, str::len);
+    Some(Position {
+        row: row as u16,
+        col: col as u16,
+    })
+}
+
+/// The class of the character at `pos`, the end of a line counting as blank.
+fn class_at(doc: &Document, pos: Position) -> CharClass {
+    char_at(doc, pos).map_or(CharClass::Blank, CharClass::of)
+}
+
+/// Whether `pos` is on an empty line, which word motions stop at like on a word.
+fn on_empty_line(doc: &Document, pos: Position) -> bool {
+    doc.get_line_len(pos.row as usize) == 0
+}
+
+fn word_start(doc: &Document, from: Position) -> Position {
+    let class = class_at(doc, from);
+    let mut pos = from;
+    // off the current word
+    while let Some(ch) = char_at(doc, pos).filter(|ch| CharClass::of(*ch) == class) {
+        if class == CharClass::Blank {
+            break;
+        }
+        pos.col += ch.len_utf8() as u16;
+    }
+    // then over the blanks up to the next one
+    while class_at(doc, pos) == CharClass::Blank {
+        let Some(next) = next_pos(doc, pos) else {
+            return pos;
+        };
+        pos = next;
+        if pos.col == 0 && on_empty_line(doc, pos) {
+            return pos;
+        }
+    }
+    pos
+}
+
+fn word_end(doc: &Document, from: Position) -> Position {
+    let Some(mut pos) = next_pos(doc, from) else {
+        return from;
+    };
+    while class_at(doc, pos) == CharClass::Blank {
+        match next_pos(doc, pos) {
+            Some(next) => pos = next,
+            None => return from,
+        }
+    }
+    let class = class_at(doc, pos);
+    loop {
+        let next = Position {
+            row: pos.row,
+            col: pos.col + char_at(doc, pos).map_or(0, char::len_utf8) as u16,
+        };
+        if class_at(doc, next) != class {
+            return pos;
+        }
+        pos = next;
+    }
+}
+
+fn word_back(doc: &Document, from: Position) -> Position {
+    let Some(mut pos) = prev_pos(doc, from) else {
+        return from;
+    };
+    while class_at(doc, pos) == CharClass::Blank {
+        if on_empty_line(doc, pos) {
+            return pos;
+        }
+        match prev_pos(doc, pos) {
+            Some(prev) => pos = prev,
+            None => return pos,
+        }
+    }
+    let class = class_at(doc, pos);
+    while let Some(prev) = prev_pos(doc, pos) {
+        if prev.row != pos.row || class_at(doc, prev) != class {
+            break;
+        }
+        pos = prev;
+    }
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(row: u16, col: u16) -> Position {
+        Position { row, col }
+    }
+
+    fn chars(start: (u16, u16), end: (u16, u16)) -> Option<TextRange> {
+        Some(TextRange::chars(pos(start.0, start.1), pos(end.0, end.1)))
+    }
+
+    fn lines(first: usize, last: usize) -> Option<TextRange> {
+        Some(TextRange::lines(first, last))
+    }
+
+    #[test]
+    fn motion_ranges() {
+        let find = |cmd, ch| Motion::Find(Find::new(cmd, ch));
+        let (down, up) = (Motion::Lines { down: true }, Motion::Lines { down: false });
+        #[rustfmt::skip]
+        let cases = [
+            // `e` takes in the character it lands on
+            ("foo bar baz", (0, 0), Motion::WordEnd, 1, chars((0, 0), (0, 3))),
+            ("foo bar baz", (0, 0), Motion::WordEnd, 2, chars((0, 0), (0, 7))),
+            ("héllo wörld", (0, 0), Motion::WordEnd, 1, chars((0, 0), (0, 6))),
+            // `w` stops short of where it lands, and at the end of the line after its last word
+            ("foo bar baz", (0, 0), Motion::WordStart, 1, chars((0, 0), (0, 4))),
+            ("foo bar baz", (0, 0), Motion::WordStart, 2, chars((0, 0), (0, 8))),
+            ("foo bar\nbaz", (0, 4), Motion::WordStart, 1, chars((0, 4), (0, 7))),
+            ("foo bar", (0, 4), Motion::WordStart, 1, chars((0, 4), (0, 7))),
+            ("foo bar", (0, 5), Motion::WordBack, 1, chars((0, 4), (0, 5))),
+            // `$` takes in the last character, `N$` on the line N - 1 down
+            ("foo bar", (0, 1), Motion::LineEnd, 1, chars((0, 1), (0, 7))),
+            ("foo\nbar", (0, 1), Motion::LineEnd, 2, chars((0, 1), (1, 3))),
+            ("foo bar", (0, 5), Motion::LineStart, 1, chars((0, 0), (0, 5))),
+            // `f` and `t` take in where they land, `F` and `T` do not
+            ("a(b)c(d)", (0, 0), find('f', '('), 2, chars((0, 0), (0, 6))),
+            ("a(b)c(d)", (0, 0), find('t', ')'), 1, chars((0, 0), (0, 3))),
+            ("a(b)c(d)", (0, 7), find('F', '('), 1, chars((0, 5), (0, 7))),
+            ("a(b)c(d)", (0, 7), find('T', '('), 1, chars((0, 6), (0, 7))),
+            ("a(b)c(d)", (0, 0), find('f', 'x'), 1, None),
+            // `j`, `k` and `G` take in whole lines
+            ("one\ntwo\nthree", (0, 1), down, 1, lines(0, 1)),
+            ("one\ntwo\nthree", (2, 0), up, 5, lines(0, 2)),
+            ("one\ntwo\nthree", (2, 0), down, 1, None),
+            ("one\ntwo\nthree", (1, 2), Motion::ToLine(None), 1, lines(1, 2)),
+            ("one\ntwo\nthree", (2, 3), Motion::ToLine(Some(1)), 1, lines(0, 2)),
+        ];
+        for (text, (row, col), motion, count, expected) in cases {
+            let doc = Document::from_text(text);
+            let range = motion.range(&doc, pos(row, col), count);
+            assert_eq!(
+                range,
+                expected,
+                "{:?} from {:?} in {:?}",
+                motion,
+                (row, col),
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn word_objects() {
+        // (line, cursor column, around, count) to the columns taken in
+        #[rustfmt::skip]
+        let cases = [
+            ("foo bar baz", 5, false, 1, Some((4, 7))),
+            ("foo bar baz", 5, true, 1, Some((4, 8))),
+            ("foo bar baz", 0, false, 3, Some((0, 7))),
+            ("foo bar baz", 0, true, 2, Some((0, 8))),
+            // on blanks, the blanks are a word, and `aw` takes in the word after them
+            ("foo   bar", 4, false, 1, Some((3, 6))),
+            ("foo   bar", 4, true, 1, Some((3, 9))),
+            // at the end of the line `aw` takes in the blanks before the word instead
+            ("foo bar", 5, true, 1, Some((3, 7))),
+            ("foo bar", 5, false, 1, Some((4, 7))),
+            ("  foo", 3, true, 1, Some((0, 5))),
+            // a run of punctuation is a word of its own
+            ("foo(bar)", 1, false, 1, Some((0, 3))),
+            ("foo(bar)", 3, false, 1, Some((3, 4))),
+            ("a->b c", 2, false, 1, Some((1, 3))),
+            ("a->b c", 2, true, 1, Some((1, 3))),
+            ("x == y", 3, true, 1, Some((2, 5))),
+            ("foo(bar)", 1, false, 3, Some((0, 7))),
+            ("héllo wörld", 1, true, 1, Some((0, 7))),
+            // past the end of the line there is no word
+            ("foo", 3, false, 1, None),
+            ("", 0, true, 1, None),
+        ];
+        for (line, col, around, count, expected) in cases {
+            let object = word_object(line, col, around, count);
+            assert_eq!(
+                object,
+                expected,
+                "{:?} at {} in {:?}",
+                (around, count),
+                col,
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn word_motions() {
+        // each stop is where the motion leads from the one before
+        type Stops = &'static [(u16, u16)];
+        let (w, e, b) = (Motion::WordStart, Motion::WordEnd, Motion::WordBack);
+        #[rustfmt::skip]
+        let cases: [(&str, Motion, Stops); 9] = [
+            ("foo_bar(baz)-qux", w, &[(0, 0), (0, 7), (0, 8), (0, 11), (0, 13)]),
+            ("foo_bar(baz)-qux", e, &[(0, 0), (0, 6), (0, 7), (0, 10), (0, 12), (0, 15)]),
+            ("foo_bar(baz)-qux", b, &[(0, 15), (0, 13), (0, 11), (0, 8), (0, 7), (0, 0)]),
+            ("naïve café-au lait", w, &[(0, 0), (0, 7), (0, 12), (0, 13), (0, 16)]),
+            ("naïve café-au lait", e, &[(0, 0), (0, 5), (0, 10), (0, 12), (0, 14)]),
+            ("naïve café-au lait", b, &[(0, 16), (0, 13), (0, 12), (0, 7), (0, 0)]),
+            // an empty line is a word to `w` and `b`, but not to `e`
+            ("foo\n\n  bar", w, &[(0, 0), (1, 0), (2, 2)]),
+            ("foo\n\n  bar", e, &[(0, 0), (0, 2), (2, 4)]),
+            ("foo\n\n  bar", b, &[(2, 2), (1, 0), (0, 0)]),
+        ];
+        for (text, motion, stops) in cases {
+            let doc = Document::from_text(text);
+            for pair in stops.windows(2) {
+                let (from, to) = (pos(pair[0].0, pair[0].1), pos(pair[1].0, pair[1].1));
+                let target = motion.target(&doc, from, 1);
+                assert_eq!(
+                    target,
+                    Some(to),
+                    "{:?} from {:?} in {:?}",
+                    motion,
+                    from,
+                    text
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn line_word_back_stops_where_b_does() {
+        let cases = [
+            ("foo_bar(baz", 8),
+            ("foo_bar(", 7),
+            ("naïve café", 7),
+            ("foo-- \t", 3),
+            ("  foo", 2),
+            ("   ", 0),
+            ("", 0),
+        ];
+        for (text, col) in cases {
+            assert_eq!(line_word_back(text), col, "{:?}", text);
+            let from = pos(0, text.len() as u16);
+            let doc = Document::from_text(text);
+            if !text.trim().is_empty() {
+                let target = Motion::WordBack.target(&doc, from, 1);
+                assert_eq!(target, Some(pos(0, col as u16)), "{:?}", text);
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use thiserror::Error;
+
+/// Editor options, changed at runtime with `:set`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub number: bool,
+    pub tabstop: u8,
+    pub shiftwidth: u8,
+    pub expandtab: bool,
+    pub autoindent: bool,
+    pub hlsearch: bool,
+    pub scrolloff: u16,
+    /// Whether a partly typed command is dropped after `timeoutlen` milliseconds without a key
+    pub timeout: bool,
+    pub timeoutlen: u16,
+}
+
+#[derive(Debug, Error)]
+pub enum OptionError {
+    #[error("Unknown option: {0}")]
+    Unknown(String),
+    #[error("Invalid argument: {0}")]
+    InvalidArg(String),
+}
+
+/// Mutable access to a single option, whatever its type.
+enum Slot<'a> {
+    Bool(&'a mut bool),
+    U8(&'a mut u8),
+    U16(&'a mut u16),
+}
+
+/// Full names of every option, in the order `:set` lists them.
+const NAMES: &[&str] = &[
+    "autoindent",
+    "expandtab",
+    "hlsearch",
+    "number",
+    "scrolloff",
+    "shiftwidth",
+    "tabstop",
+    "timeout",
+    "timeoutlen",
+];
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            number: false,
+            tabstop: 8,
+            shiftwidth: 8,
+            expandtab: false,
+            autoindent: false,
+            hlsearch: true,
+            scrolloff: 0,
+            timeout: false,
+            timeoutlen: 1000,
+        }
+    }
+}
+
+impl Options {
+    /// Apply a single `:set` argument (`name`, `noname`, `name!`, `name=value` or `name?`),
+    /// returning the text to show when the argument is a query.
+    pub fn set(&mut self, arg: &str) -> Result<Option<String>, OptionError> {
+        if let Some(name) = arg.strip_suffix('?') {
+            let (name, slot) = self.slot(name)?;
+            return Ok(Some(slot.show(name)));
+        }
+        if let Some((name, value)) = arg.split_once('=') {
+            let (_, mut slot) = self.slot(name)?;
+            slot.assign(value)
+                .map_err(|_| OptionError::InvalidArg(arg.to_string()))?;
+            return Ok(None);
+        }
+        if let Some(name) = arg.strip_suffix('!') {
+            return match self.slot(name)? {
+                (_, Slot::Bool(flag)) => {
+                    *flag = !*flag;
+                    Ok(None)
+                }
+                _ => Err(OptionError::InvalidArg(arg.to_string())),
+            };
+        }
+
+        match self.slot(arg) {
+            Ok((_, Slot::Bool(flag))) => *flag = true,
+            // `:set ts` shows the value like `:set ts?` does
+            Ok((name, slot)) => return Ok(Some(slot.show(name))),
+            Err(err) => match arg.strip_prefix("no").map(|name| self.slot(name)) {
+                Some(Ok((_, Slot::Bool(flag)))) => *flag = false,
+                Some(Ok(_)) => return Err(OptionError::InvalidArg(arg.to_string())),
+                _ => return Err(err),
+            },
+        }
+        Ok(None)
+    }
+
+    /// Every option whose value differs from its default, as `:set` shows it.
+    pub fn changed(&self) -> Vec<String> {
+        let mut current = self.clone();
+        let mut default = Options::default();
+        NAMES
+            .iter()
+            .filter_map(|name| {
+                let (_, slot) = current.slot(name).ok()?;
+                let (_, default) = default.slot(name).ok()?;
+                let shown = slot.show(name);
+                (shown != default.show(name)).then_some(shown)
+            })
+            .collect()
+    }
+
+    /// Look an option up by its full or short name, returning its full name along with it.
+    fn slot(&mut self, name: &str) -> Result<(&'static str, Slot<'_>), OptionError> {
+        Ok(match name {
+            "ai" | "autoindent" => ("autoindent", Slot::Bool(&mut self.autoindent)),
+            "et" | "expandtab" => ("expandtab", Slot::Bool(&mut self.expandtab)),
+            "hls" | "hlsearch" => ("hlsearch", Slot::Bool(&mut self.hlsearch)),
+            "nu" | "number" => ("number", Slot::Bool(&mut self.number)),
+            "so" | "scrolloff" => ("scrolloff", Slot::U16(&mut self.scrolloff)),
+            "sw" | "shiftwidth" => ("shiftwidth", Slot::U8(&mut self.shiftwidth)),
+            "ts" | "tabstop" => ("tabstop", Slot::U8(&mut self.tabstop)),
+            "to" | "timeout" => ("timeout", Slot::Bool(&mut self.timeout)),
+            "tm" | "timeoutlen" => ("timeoutlen", Slot::U16(&mut self.timeoutlen)),
+            _ => return Err(OptionError::Unknown(name.to_string())),
+        })
+    }
+}
+
+impl Slot<'_> {
+    fn show(&self, name: &str) -> String {
+        match self {
+            Slot::Bool(true) => name.to_string(),
+            Slot::Bool(false) => format!("no{}", name),
+            Slot::U8(value) => format!("{}={}", name, value),
+            Slot::U16(value) => format!("{}={}", name, value),
+        }
+    }
+
+    fn assign(&mut self, value: &str) -> Result<(), ()> {
+        match self {
+            Slot::Bool(_) => return Err(()),
+            Slot::U8(slot) => **slot = value.parse().map_err(|_| ())?,
+            Slot::U16(slot) => **slot = value.parse().map_err(|_| ())?,
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+
+/// Text held in a register: whole lines, characters within a line, or a rectangle of columns
+/// cut from consecutive lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Register {
+    Linewise(Vec<String>),
+    Charwise(String),
+    Blockwise(Vec<String>),
+}
+
+/// Every register of the session: the unnamed one, `"0` to `"9` and `"a` to `"z`, the latter
+/// appended to when written as `"A` to `"Z`, and `"+` (or `"*`) standing for the system clipboard.
+#[derive(Debug, Default)]
+pub struct Registers {
+    unnamed: Option<Register>,
+    numbered: [Option<Register>; 10],
+    named: BTreeMap<char, Register>,
+    clipboard: Option<Register>,
+    /// Whether `"+` was written since the system clipboard last got it.
+    clipboard_written: bool,
+}
+
+impl Register {
+    /// The content on a single line, control characters such as newlines shown as `^J`, cut off
+    /// after `width` characters.
+    pub fn preview(&self, width: usize) -> String {
+        let text: String = match self {
+            Register::Linewise(lines) => lines.iter().map(|ln| format!("{}\n", ln)).collect(),
+            Register::Charwise(text) => text.clone(),
+            Register::Blockwise(lines) => lines.join("\n"),
+        };
+        let mut preview = String::new();
+        for (count, ch) in text.chars().enumerate() {
+            if count == width {
+                preview.push('…');
+                break;
+            }
+            match ch {
+                '\x7f' => preview.push_str("^?"),
+                ch if ch.is_control() && (ch as u32) < 0x20 => {
+                    preview.push('^');
+                    preview.push((ch as u8 + b'@') as char);
+                }
+                ch => preview.push(ch),
+            }
+        }
+        preview
+    }
+
+    /// This content followed by `more`, as whole lines if either is, a block only appending to a
+    /// block.
+    fn append(self, more: Register) -> Register {
+        match (self, more) {
+            (Register::Blockwise(mut lines), Register::Blockwise(more)) => {
+                lines.extend(more);
+                Register::Blockwise(lines)
+            }
+            (Register::Blockwise(lines), more) => Register::Linewise(lines).append(more),
+            (held, Register::Blockwise(more)) => held.append(Register::Linewise(more)),
+            (Register::Charwise(text), Register::Charwise(more)) => {
+                Register::Charwise(text + &more)
+            }
+            (Register::Linewise(mut lines), Register::Linewise(more)) => {
+                lines.extend(more);
+                Register::Linewise(lines)
+            }
+            (Register::Linewise(mut lines), Register::Charwise(more)) => {
+                lines.extend(more.split('\n').map(str::to_string));
+                Register::Linewise(lines)
+            }
+            (Register::Charwise(text), Register::Linewise(more)) => {
+                let mut lines: Vec<_> = text.split('\n').map(str::to_string).collect();
+                lines.extend(more);
+                Register::Linewise(lines)
+            }
+        }
+    }
+
+    /// The content of plain `text`, whole lines if it ends with a newline.
+    pub fn from_text(text: String) -> Register {
+        match text.strip_suffix('\n') {
+            Some(lines) => Register::Linewise(lines.split('\n').map(str::to_string).collect()),
+            None => Register::Charwise(text),
+        }
+    }
+
+    /// The content on a single line, the lines joined by spaces.
+    pub fn joined(&self) -> String {
+        match self {
+            Register::Linewise(lines) | Register::Blockwise(lines) => lines.join(" "),
+            Register::Charwise(text) => text.clone(),
+        }
+    }
+}
+
+impl Registers {
+    /// Whether `name` is a register that text can be yanked or deleted into.
+    pub fn is_writable(name: char) -> bool {
+        name == '"' || name.is_ascii_alphabetic() || Self::is_clipboard(name)
+    }
+
+    /// Whether `name` is `"+` or `"*`, both of which stand for the system clipboard.
+    pub fn is_clipboard(name: char) -> bool {
+        matches!(name, '+' | '*')
+    }
+
+    /// Whether `name` is a register that text can be put from.
+    pub fn is_readable(name: char) -> bool {
+        Self::is_writable(name) || name.is_ascii_digit()
+    }
+
+    /// Store yanked text in register `name`, or in `"0` if none is given. The unnamed register
+    /// always gets it too.
+    pub fn yank(&mut self, name: Option<char>, content: Register) {
+        match name {
+            Some(name) if name.is_ascii_alphabetic() => self.store_named(name, content),
+            Some(name) if Self::is_clipboard(name) => self.store_clipboard(content),
+            _ => {
+                self.numbered[0] = Some(content.clone());
+                self.unnamed = Some(content);
+            }
+        }
+    }
+
+    /// Store deleted text in register `name`, or in `"1` if none is given and it is not within a
+    /// line, shifting the older deletions down to `"9`. The unnamed register always gets it too.
+    pub fn delete(&mut self, name: Option<char>, content: Register) {
+        match name {
+            Some(name) if name.is_ascii_alphabetic() => return self.store_named(name, content),
+            Some(name) if Self::is_clipboard(name) => return self.store_clipboard(content),
+            _ if matches!(&content, Register::Charwise(text) if !text.contains('\n')) => {}
+            _ => {
+                self.numbered[1..].rotate_right(1);
+                self.numbered[1] = Some(content.clone());
+            }
+        }
+        self.unnamed = Some(content);
+    }
+
+    /// Store the keys of a macro recorded with `q` in named register `name`, leaving the unnamed
+    /// register alone.
+    pub fn record(&mut self, name: char, keys: String) {
+        self.append_named(name, Register::Charwise(keys));
+    }
+
+    /// Put `content` into named register `name`, or after what it holds for an uppercase `name`,
+    /// and have the unnamed register mirror it.
+    fn store_named(&mut self, name: char, content: Register) {
+        self.unnamed = Some(self.append_named(name, content));
+    }
+
+    /// Put `content` into `"+`, to be copied onto the system clipboard, and have the unnamed
+    /// register mirror it.
+    fn store_clipboard(&mut self, content: Register) {
+        self.clipboard = Some(content.clone());
+        self.clipboard_written = true;
+        self.unnamed = Some(content);
+    }
+
+    /// What was yanked or deleted into `"+` since the last call, for the system clipboard.
+    pub fn take_clipboard_write(&mut self) -> Option<Register> {
+        match self.clipboard_written {
+            true => {
+                self.clipboard_written = false;
+                self.clipboard.clone()
+            }
+            false => None,
+        }
+    }
+
+    /// Have `"+` hold what the system clipboard does.
+    pub fn set_clipboard(&mut self, content: Option<Register>) {
+        self.clipboard = content;
+    }
+
+    /// Put `content` into named register `name`, or after what it holds for an uppercase `name`,
+    /// returning what the register ends up holding.
+    fn append_named(&mut self, name: char, content: Register) -> Register {
+        let lower = name.to_ascii_lowercase();
+        let content = match self.named.remove(&lower) {
+            Some(held) if name.is_ascii_uppercase() => held.append(content),
+            _ => content,
+        };
+        self.named.insert(lower, content.clone());
+        content
+    }
+
+    /// The content of register `name`, if it holds any.
+    pub fn get(&self, name: char) -> Option<&Register> {
+        match name {
+            '"' => self.unnamed.as_ref(),
+            '0'..='9' => self.numbered[name as usize - '0' as usize].as_ref(),
+            '+' | '*' => self.clipboard.as_ref(),
+            name => self.named.get(&name.to_ascii_lowercase()),
+        }
+    }
+
+    /// The registers holding something, in the order `:registers` lists them.
+    pub fn list(&self) -> Vec<(char, &Register)> {
+        let unnamed = self.unnamed.iter().map(|reg| ('"', reg));
+        let numbered = (self.numbered.iter().enumerate())
+            .filter_map(|(ind, reg)| Some((char::from(b'0' + ind as u8), reg.as_ref()?)));
+        let named = self.named.iter().map(|(name, reg)| (*name, reg));
+        let clipboard = self.clipboard.iter().map(|reg| ('+', reg));
+        unnamed
+            .chain(numbered)
+            .chain(named)
+            .chain(clipboard)
+            .collect()
+    }
+}
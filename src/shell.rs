@@ -0,0 +1,42 @@
+use std::{
+    env,
+    io::{self, Write},
+    process::{Command, ExitStatus, Output, Stdio},
+    thread,
+};
+
+fn command(cmd: &str) -> Command {
+    let shell = env::var("SHELL").unwrap_or_else(|_| String::from("sh"));
+    let mut command = Command::new(shell);
+    command.arg("-c").arg(cmd);
+    command
+}
+
+/// Run `cmd` with the user's shell, attached to the terminal.
+pub fn run(cmd: &str) -> io::Result<ExitStatus> {
+    command(cmd).status()
+}
+
+/// Run `cmd` with the user's shell, capturing what it prints.
+pub fn capture(cmd: &str) -> io::Result<Output> {
+    command(cmd).stdin(Stdio::null()).output()
+}
+
+/// Run `cmd` with the user's shell, feeding it `input` and capturing what it prints.
+pub fn filter(cmd: &str, input: String) -> io::Result<Output> {
+    let mut child = command(cmd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // written from another thread so that a chatty command can't block on a full stdout pipe
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output()?;
+    match writer.join() {
+        Ok(Err(err)) if err.kind() != io::ErrorKind::BrokenPipe => return Err(err),
+        _ => {}
+    }
+    Ok(output)
+}
@@ -0,0 +1,129 @@
+use std::path::Path;
+
+/// How the source of one language is highlighted, line by line.
+#[derive(Debug)]
+pub struct Syntax {
+    pub name: &'static str,
+    extensions: &'static [&'static str],
+    keywords: &'static [&'static str],
+    line_comment: &'static str,
+    quotes: &'static [char],
+}
+
+/// What a highlighted stretch of a line is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Keyword,
+    Number,
+    String,
+    Comment,
+}
+
+/// The languages vix knows how to highlight.
+const SYNTAXES: &[Syntax] = &[
+    Syntax {
+        name: "rust",
+        extensions: &["rs"],
+        keywords: &[
+            "as", "break", "const", "continue", "crate", "else", "enum", "false", "fn", "for",
+            "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+            "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+            "unsafe", "use", "where", "while",
+        ],
+        line_comment: "//",
+        quotes: &['"'],
+    },
+    Syntax {
+        name: "c",
+        extensions: &["c", "h", "cc", "cpp", "hpp"],
+        keywords: &[
+            "break", "case", "char", "const", "continue", "default", "do", "double", "else",
+            "enum", "extern", "float", "for", "goto", "if", "int", "long", "return", "short",
+            "signed", "sizeof", "static", "struct", "switch", "typedef", "union", "unsigned",
+            "void", "volatile", "while",
+        ],
+        line_comment: "//",
+        quotes: &['"', '\''],
+    },
+    Syntax {
+        name: "python",
+        extensions: &["py"],
+        keywords: &[
+            "and", "as", "assert", "break", "class", "continue", "def", "del", "elif", "else",
+            "except", "False", "finally", "for", "from", "if", "import", "in", "is", "lambda",
+            "None", "not", "or", "pass", "raise", "return", "True", "try", "while", "with",
+            "yield",
+        ],
+        line_comment: "#",
+        quotes: &['"', '\''],
+    },
+    Syntax {
+        name: "sh",
+        extensions: &["sh", "bash"],
+        keywords: &[
+            "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+            "in", "local", "return", "then", "while",
+        ],
+        line_comment: "#",
+        quotes: &['"', '\''],
+    },
+];
+
+/// The syntax of the file at `path`, going by its extension.
+pub fn detect(path: Option<&Path>) -> Option<&'static Syntax> {
+    let ext = path?.extension()?.to_str()?;
+    SYNTAXES
+        .iter()
+        .find(|syntax| syntax.extensions.contains(&ext))
+}
+
+impl Syntax {
+    /// Split `line` into highlighted stretches, as byte ranges `start..end`.
+    pub fn highlight(&self, line: &str) -> Vec<(usize, usize, Token)> {
+        let mut tokens = Vec::new();
+        let mut start = 0;
+        while let Some(ch) = line[start..].chars().next() {
+            let rest = &line[start..];
+            if rest.starts_with(self.line_comment) {
+                tokens.push((start, line.len(), Token::Comment));
+                break;
+            }
+
+            let end = if self.quotes.contains(&ch) {
+                let end = string_end(rest, ch);
+                tokens.push((start, start + end, Token::String));
+                start + end
+            } else if ch.is_alphanumeric() || ch == '_' {
+                let len = rest
+                    .find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+                    .unwrap_or(rest.len());
+                let word = &rest[..len];
+                if ch.is_ascii_digit() {
+                    tokens.push((start, start + len, Token::Number));
+                } else if self.keywords.contains(&word) {
+                    tokens.push((start, start + len, Token::Keyword));
+                }
+                start + len
+            } else {
+                start + ch.len_utf8()
+            };
+            start = end;
+        }
+        tokens
+    }
+}
+
+/// The length of the string opening `text` with `quote`, up to the end of the line if it is not
+/// closed.
+fn string_end(text: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (ind, ch) in text.char_indices().skip(1) {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ch if ch == quote => return ind + ch.len_utf8(),
+            _ => {}
+        }
+    }
+    text.len()
+}
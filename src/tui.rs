@@ -5,6 +5,7 @@ use std::{
 
 use crossterm::{
     cursor::{self, SetCursorStyle},
+    event::{self, Event, KeyEventKind},
     execute,
     terminal::{self, disable_raw_mode, enable_raw_mode},
 };
@@ -17,16 +18,37 @@ pub fn init() -> io::Result<Terminal<CrosstermBackend<Stdout>>> {
         hook(panic_info);
     }));
 
+    enter()?;
+    Terminal::new(CrosstermBackend::new(stdout()))
+}
+
+/// Prepare the terminal for drawing, also used to come back after a [`restore`].
+pub fn enter() -> io::Result<()> {
     enable_raw_mode()?;
     execute!(stdout(), terminal::EnterAlternateScreen)?;
     execute!(stdout(), cursor::SavePosition)?;
     execute!(stdout(), cursor::EnableBlinking)?;
+    execute!(stdout(), event::EnableBracketedPaste)?;
     // execute!(stdout(), event::EnableMouseCapture)?;
-    Terminal::new(CrosstermBackend::new(stdout()))
+    Ok(())
+}
+
+/// Block until a key is pressed, with the terminal restored.
+pub fn wait_for_key() -> io::Result<()> {
+    enable_raw_mode()?;
+    loop {
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press {
+                break;
+            }
+        }
+    }
+    disable_raw_mode()
 }
 
 pub fn restore() -> io::Result<()> {
     // execute!(stdout(), event::DisableMouseCapture)?;
+    execute!(stdout(), event::DisableBracketedPaste)?;
     execute!(stdout(), cursor::DisableBlinking)?;
     execute!(stdout(), cursor::RestorePosition)?;
     execute!(stdout(), terminal::LeaveAlternateScreen)?;